@@ -140,7 +140,7 @@ Instantiate() {
     
     #read from FILE_CODE_ID
     CODE_ID=$(cat $FILE_CODE_ID)
-    junod tx wasm instantiate $CODE_ID '{"owner":"'$ADDR_MARBLE'", "reward_token_address":"'$REWARD_TOKEN_ADDRESS'", "stake_token_address":"'$STAKE_TOKEN_ADDRESS'", "daily_reward_amount":"95890000000", "apy_prefix":"10000", "reward_interval":86400, "delta_time":0, "lock_days":14, "enabled":true}' --label "BLOCK-ATOM Incentive" $WALLET $TXFLAG -y
+    junod tx wasm instantiate $CODE_ID '{"owner":"'$ADDR_MARBLE'", "reward_token":{"cw20":"'$REWARD_TOKEN_ADDRESS'"}, "stake_token":{"cw20":"'$STAKE_TOKEN_ADDRESS'"}, "daily_reward_amount":"95890000000", "apy_prefix":"10000", "reward_interval":86400, "delta_time":0, "lock_days":14, "enabled":true}' --label "BLOCK-ATOM Incentive" $WALLET $TXFLAG -y
 }
 
 #Get Instantiated Contract Address
@@ -167,22 +167,25 @@ GetContractAddress() {
 #Send initial tokens
 SendReward() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $REWARD_TOKEN_ADDRESS '{"send":{"amount":"5154298000000","contract":"'$CONTRACT_INCENTIVE'","msg":""}}' $WALLET $TXFLAG -y
+    #reward program runs for 60 days from funding
+    MSG=$(echo -n '{"initial_fund":{"duration":5184000}}' | base64 -w 0)
+    junod tx wasm execute $REWARD_TOKEN_ADDRESS '{"send":{"amount":"5154298000000","contract":"'$CONTRACT_INCENTIVE'","msg":"'$MSG'"}}' $WALLET $TXFLAG -y
 }
 
 SendStake() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $STAKE_TOKEN_ADDRESS '{"send":{"amount":"1000000","contract":"'$CONTRACT_INCENTIVE'","msg":""}}' $WALLET $TXFLAG -y
+    MSG=$(echo -n '{"stake":{}}' | base64 -w 0)
+    junod tx wasm execute $STAKE_TOKEN_ADDRESS '{"send":{"amount":"1000000","contract":"'$CONTRACT_INCENTIVE'","msg":"'$MSG'"}}' $WALLET $TXFLAG -y
 }
 
 RemoveStaker() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"remove_staker":{"address":"'$ADDR_MARBLE'"}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"remove_staker":{"pool_id":0,"address":"'$ADDR_MARBLE'"}}' $WALLET $TXFLAG -y
 }
 
 RemoveAllStakers() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"remove_all_stakers":{}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"remove_all_stakers":{"pool_id":0}}' $WALLET $TXFLAG -y
 }
 
 WithdrawReward() {
@@ -190,34 +193,56 @@ WithdrawReward() {
     junod tx wasm execute $CONTRACT_INCENTIVE '{"withdraw_reward":{}}' $WALLET $TXFLAG -y
 }
 
-WithdrawStake() {
-    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"withdraw_stake":{}}' $WALLET $TXFLAG -y
-}
-
 ClaimReward() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"claim_reward":{}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"claim_reward":{"pool_id":0}}' $WALLET $TXFLAG -y
 }
 
 Unstake() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"create_unstake":{"unstake_amount":"100000"}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"create_unstake":{"pool_id":0,"unstake_amount":"100000"}}' $WALLET $TXFLAG -y
 }
 
 FetchUnstake() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"fetch_unstake":{"index":0}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"fetch_unstake":{"pool_id":0,"id":0}}' $WALLET $TXFLAG -y
+}
+
+FetchAllMatured() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"fetch_all_matured":{"pool_id":0}}' $WALLET $TXFLAG -y
+}
+
+ProposeOwner() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"propose_owner":{"new_owner":"'$ADDR_ADMIN'"}}' $WALLET $TXFLAG -y
 }
 
-UpdateConfig() {
+AcceptOwnership() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"update_config":{"new_owner":"'$ADDR_ADMIN'"}}' $WALLET $TXFLAG -y
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"accept_ownership":{}}' $WALLET $TXFLAG -y
 }
 
 UpdateConstants() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod tx wasm execute $CONTRACT_INCENTIVE '{"update_constants":{"daily_reward_amount":"10000000000", "apy_prefix":"10000", "reward_interval":300, "lock_days":0,"enabled":true}}' $WALLET $TXFLAG -y
+    ETA=$(($(date +%s) + 86400 + 600))
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"update_constants":{"reward_interval":300, "delta_time":0, "lock_days":0,"early_unstake_penalty":{"percent":50,"decay":true,"destination":"redistribute"},"min_delay":86400,"eta":'$ETA'}}' $WALLET $TXFLAG -y
+}
+
+ExecuteConstantsChange() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"execute_constants_change":{"id":0}}' $WALLET $TXFLAG -y
+}
+
+AddPool() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    #BLOCK-MARBLE-LP
+    junod tx wasm execute $CONTRACT_INCENTIVE '{"add_pool":{"stake_token":{"cw20":"juno17sf282da63uwxfht9nuzslgan4jh7qvya0k78mc9v3muuckvjndsu8dy9m"},"alloc_point":100}}' $WALLET $TXFLAG -y
+}
+
+PrintPools() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"list_pools":{}}' $NODECHAIN
 }
 
 PrintConfig() {
@@ -227,22 +252,50 @@ PrintConfig() {
 
 PrintStaker() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"staker":{"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"staker":{"pool_id":0,"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
 }
 
 PrintListStakers() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"list_stakers":{}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"list_stakers":{"pool_id":0}}' $NODECHAIN
 }
 
 PrintUnstaking() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"unstaking":{"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"unstaking":{"pool_id":0,"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
 }
 
 PrintAPY() {
     CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
-    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"apy":{}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"apy":{"pool_id":0}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"apr":{"pool_id":0}}' $NODECHAIN
+}
+
+PrintPendingReward() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"pending_reward":{"pool_id":0,"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
+}
+
+PrintTvl() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"tvl":{}}' $NODECHAIN
+}
+
+PrintVotingPower() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"voting_power_at_height":{"address":"'$ADDR_MARBLE'"}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"total_power_at_height":{}}' $NODECHAIN
+}
+
+PrintHooks() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"hooks":{}}' $NODECHAIN
+}
+
+PrintSolvency() {
+    CONTRACT_INCENTIVE=$(cat $FILE_CONTRACT_ADDR)
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"solvency":{}}' $NODECHAIN
+    junod query wasm contract-state smart $CONTRACT_INCENTIVE '{"solvency_stakers":{"pool_id":0}}' $NODECHAIN
 }
 
 #################################################################################
@@ -1,32 +1,62 @@
 #[cfg(not(feature = "library"))]
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
-    attr, to_binary, Binary, Deps, DepsMut, Env, MessageInfo, Response, StdResult, Uint128,
-    WasmMsg, WasmQuery, QueryRequest,Order, Addr, Storage
+    attr, coins, from_json, to_json_binary, Api, Decimal, BankMsg, Binary, Deps, DepsMut, Env, MessageInfo, QuerierWrapper, Reply, Response, StdError,
+    StdResult, SubMsg, Uint128, Uint256, WasmMsg, WasmQuery, QueryRequest,Order, Addr, Storage, CosmosMsg
 };
 use cw2::{get_contract_version, set_contract_version};
-use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Cw20QueryMsg};
-use cw20::{TokenInfoResponse};
-use cw_utils::{maybe_addr};
+use cw20::{Cw20ExecuteMsg, Cw20ReceiveMsg, Cw20QueryMsg, Denom};
+use cw20::{BalanceResponse, TokenInfoResponse};
+use cw_utils::{maybe_addr, one_coin, Expiration};
 use cw_storage_plus::Bound;
+use std::convert::TryFrom;
 use crate::error::ContractError;
 use crate::msg::{
-    ConfigResponse, ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg, StakerListResponse, StakerInfo, StakerResponse
+    AprResponse, ApyResponse, ConfigResponse, ConstantsChangeResponse, ExecuteMsg, HooksResponse, InstantiateMsg, MigrateMsg, PairInfoResponse, PairQueryMsg, PendingOwnerResponse, PendingRewardResponse, PoolResponse, RolesResponse, QueryMsg, ReceiveMsg, RewardAmount,
+    RewardTokenResponse, SolvencyResponse, StakerListResponse, StakerInfo, StakerResponse, StakeChangedHookMsg, StakerRewardResponse, StakerSolvencyResponse, TotalPowerAtHeightResponse, TvlResponse, VotingPowerAtHeightResponse
 };
 use crate::state::{
-    Config, CONFIG, STAKERS, UNSTAKING
+    denom_key, ChangeKind, Config, ConstantsChange, EarlyUnstakePenalty, LockTier, PenaltyDestination, Pool, RewardToken, Role, Staker, CONFIG, CONSTANTS_CHANGES, CONSTANTS_CHANGE_COUNT, HOOKS, LOCK_EXPIRIES, PENDING_OWNER, ROLES, POOLS, POOL_COUNT, POOL_IDS, POOL_REWARDS, PRICES, REWARD_TOKENS, STAKED_AMOUNTS, STAKERS, STAKER_REWARDS, TOTAL_STAKED,
+    UNSTAKE_COUNT, UNSTAKING
 };
 
 // Version info, for migration info
 const CONTRACT_NAME: &str = "marbleincentive";
 const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
-const MULTIPLE:u128 = 10_000_000_000u128;
+// Scale of the reward accumulators, fine enough for a 6 decimal reward on 18 decimal stake
+const MULTIPLE:u128 = 1_000_000_000_000_000_000_000_000_000u128;
+
+// Apy is reported scaled by 10^10
+const APY_MULTIPLE:u128 = 10_000_000_000u128;
+
+// Weight of the pool created on instantiate
+const DEFAULT_ALLOC_POINT: u64 = 100;
+
+// Timelock of UpdateConstants unless set on instantiate
+const DEFAULT_MIN_DELAY: u64 = 86400;
+
+const SECONDS_PER_YEAR: u64 = 365 * 86400;
+
+// Reply to a hook sent from EmergencyWithdraw, which a failing hook must not hold
+const HOOK_REPLY_ID: u64 = 1;
+
+// Lock tier multipliers are in percent
+const MULTIPLIER_BASE: u64 = 100;
+
+fn default_lock_tiers() -> Vec<LockTier> {
+    vec![
+        LockTier { days: 0, multiplier: 100 },
+        LockTier { days: 30, multiplier: 125 },
+        LockTier { days: 90, multiplier: 150 },
+        LockTier { days: 365, multiplier: 200 },
+    ]
+}
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     deps: DepsMut,
-    _env: Env,
+    env: Env,
     info: MessageInfo,
     msg: InstantiateMsg,
 ) -> StdResult<Response> {
@@ -36,21 +66,64 @@ pub fn instantiate(
         .owner
         .map_or(Ok(info.sender), |o| deps.api.addr_validate(&o))?;
 
+    let lock_tiers = msg.lock_tiers.unwrap_or_else(default_lock_tiers);
+    if !valid_lock_tiers(&lock_tiers) {
+        return Err(StdError::generic_err("Invalid lock tiers"));
+    }
+    let pair_address = msg.pair_address
+        .map(|pair| deps.api.addr_validate(&pair))
+        .transpose()?;
+
     let config = Config {
-        owner: Some(owner.clone()),
-        reward_token_address: msg.reward_token_address,
-        stake_token_address: msg.stake_token_address,
-        reward_amount: Uint128::zero(),
-        stake_amount: Uint128::zero(),
-        daily_reward_amount: msg.daily_reward_amount,
-        apy_prefix: msg.apy_prefix,
+        owner: Some(owner),
         reward_interval: msg.reward_interval,
         delta_time: msg.delta_time,
         lock_days: msg.lock_days,
-        enabled: true
+        enabled: true,
+        total_alloc_point: DEFAULT_ALLOC_POINT,
+        lock_tiers,
+        // FetchEarly is refused until the owner sets a penalty below 100 percent
+        early_unstake_penalty: EarlyUnstakePenalty {
+            percent: 100,
+            decay: false,
+            destination: PenaltyDestination::Redistribute
+        },
+        min_delay: msg.min_delay.unwrap_or(DEFAULT_MIN_DELAY),
+        pair_address
     };
     CONFIG.save(deps.storage, &config)?;
 
+    let reward_token = validate_denom(deps.api, msg.reward_token)?;
+    let stake_token = validate_denom(deps.api, msg.stake_token)?;
+    if reward_token == stake_token {
+        return Err(StdError::generic_err("Stake token cannot be a reward token"));
+    }
+
+    REWARD_TOKENS.save(deps.storage, denom_key(&reward_token), &RewardToken {
+        denom: reward_token,
+        reward_amount: Uint128::zero(),
+        reward_owed: Uint128::zero(),
+        daily_reward_amount: msg.daily_reward_amount,
+        apy_prefix: msg.apy_prefix,
+        reward_per_point_stored: Uint256::zero(),
+        last_update_time: env.block.time.seconds(),
+        period_finish: 0u64
+    })?;
+
+    POOL_IDS.save(deps.storage, denom_key(&stake_token), &0u64)?;
+    POOLS.save(deps.storage, 0u64, &Pool {
+        stake_token,
+        total_active_stake: Uint128::zero(),
+        total_unbonding: Uint128::zero(),
+        total_weight: Uint128::zero(),
+        penalty_amount: Uint128::zero(),
+        penalty_per_weight: Uint256::zero(),
+        alloc_point: DEFAULT_ALLOC_POINT,
+        retired: false,
+        last_update_time: env.block.time.seconds()
+    })?;
+    POOL_COUNT.save(deps.storage, &1u64)?;
+
     Ok(Response::default())
 }
 
@@ -62,536 +135,2019 @@ pub fn execute(
     msg: ExecuteMsg,
 ) -> Result<Response, ContractError> {
     match msg {
-        ExecuteMsg::UpdateConfig { new_owner } => execute_update_config(deps, info, new_owner),
-        ExecuteMsg::UpdateConstants { daily_reward_amount, apy_prefix , reward_interval, delta_time, lock_days, enabled} => execute_update_constants(deps, info, daily_reward_amount, apy_prefix, reward_interval, delta_time, lock_days, enabled),
+        ExecuteMsg::ProposeOwner { new_owner, expiry } => execute_propose_owner(deps, env, info, new_owner, expiry),
+        ExecuteMsg::AcceptOwnership {} => execute_accept_ownership(deps, env, info),
+        ExecuteMsg::CancelOwnershipProposal {} => execute_cancel_ownership_proposal(deps, info),
+        ExecuteMsg::RenounceOwnership {} => execute_renounce_ownership(deps, info),
+        ExecuteMsg::GrantRole { address, role } => execute_set_role(deps, info, address, role, true),
+        ExecuteMsg::RevokeRole { address, role } => execute_set_role(deps, info, address, role, false),
+        ExecuteMsg::SetEnabled { enabled } => execute_set_enabled(deps, info, enabled),
+        ExecuteMsg::UpdateConstants { reward_interval, delta_time, lock_days, early_unstake_penalty, min_delay, eta } => execute_update_constants(deps, env, info, ChangeKind::Constants {
+            reward_interval,
+            delta_time,
+            lock_days,
+            early_unstake_penalty,
+            min_delay
+        }, eta),
+        ExecuteMsg::ExecuteConstantsChange { id } => execute_constants_change(deps, env, info, id),
+        ExecuteMsg::CancelConstantsChange { id } => execute_cancel_constants_change(deps, info, id),
+        ExecuteMsg::UpdateLockTiers { lock_tiers, eta } => execute_update_lock_tiers(deps, env, info, lock_tiers, eta),
+        ExecuteMsg::AddRewardToken { token, apy_prefix } => execute_add_reward_token(deps, env, info, token, apy_prefix),
+        ExecuteMsg::UpdateRewardToken { token, daily_reward_amount, apy_prefix, eta } => execute_update_reward_token(deps, env, info, token, daily_reward_amount, apy_prefix, eta),
+        ExecuteMsg::SetPrice { pool_id, token, price } => execute_set_price(deps, info, pool_id, token, price),
+        ExecuteMsg::SetPairAddress { pair_address } => execute_set_pair_address(deps, info, pair_address),
+        ExecuteMsg::AddPool { stake_token, alloc_point } => execute_add_pool(deps, env, info, stake_token, alloc_point),
+        ExecuteMsg::SetPool { pool_id, alloc_point } => execute_set_pool(deps, env, info, pool_id, alloc_point, false),
+        ExecuteMsg::RetirePool { pool_id } => execute_set_pool(deps, env, info, pool_id, 0u64, true),
         ExecuteMsg::Receive(msg) => try_receive(deps, env, info, msg),
-        ExecuteMsg::WithdrawReward {} => try_withdraw_reward(deps, info),
-        ExecuteMsg::WithdrawStake {} => try_withdraw_stake(deps, info),
-        ExecuteMsg::ClaimReward {} => try_claim_reward(deps, env, info),
-        ExecuteMsg::CreateUnstake {unstake_amount} => try_create_unstake(deps, env, info, unstake_amount),
-        ExecuteMsg::FetchUnstake {index} => try_fetch_unstake(deps, env, info, index),
-        ExecuteMsg::AddStakers { stakers } => execute_add_stakers(deps, info, stakers),
-        ExecuteMsg::RemoveStaker { address } => execute_remove_staker(deps, info, address),
-        ExecuteMsg::RemoveAllStakers { start_after, limit } => execute_remove_all_stakers(deps, info, start_after, limit),
-    }
-}
-
-// ++ Added: Update staked amount when unbonding is created
-// pub fn update_stake_amount (
-//     storage: &mut dyn Storage,
-//     env: Env,
-//     unstake_amount: Uint128
-// ) -> Result<Response, ContractError> {
-      
-//     let mut cfg = CONFIG.load(storage)?;
-//     cfg.stake_amount = cfg.stake_amount - unstake_amount;
-//     CONFIG.save(storage, &cfg)?;
-
-//     Ok(Response::default())
-// }
+        ExecuteMsg::Stake { lock_days } => try_receive_native(deps, env, info, ReceiveMsg::Stake { lock_days }),
+        ExecuteMsg::StakeFor { recipient } => try_receive_native(deps, env, info, ReceiveMsg::StakeFor { recipient }),
+        ExecuteMsg::InitialFund { duration } => try_receive_native(deps, env, info, ReceiveMsg::InitialFund { duration }),
+        ExecuteMsg::WithdrawReward {} => try_withdraw_reward(deps, env, info),
+        ExecuteMsg::RecoverTokens { token, amount, recipient } => execute_recover_tokens(deps, env, info, token, amount, recipient),
+        ExecuteMsg::AddHook { addr } => execute_add_hook(deps, info, addr),
+        ExecuteMsg::RemoveHook { addr } => execute_remove_hook(deps, info, addr),
+        ExecuteMsg::ClaimReward { pool_id } => try_claim_reward(deps, env, info, pool_id),
+        ExecuteMsg::CreateUnstake { pool_id, unstake_amount } => try_create_unstake(deps, env, info, pool_id, unstake_amount),
+        ExecuteMsg::FetchUnstake { pool_id, id } => try_fetch_unstake(deps, env, info, pool_id, id),
+        ExecuteMsg::CancelUnstake { pool_id, id, amount } => try_cancel_unstake(deps, env, info, pool_id, id, amount),
+        ExecuteMsg::FetchEarly { pool_id, id } => try_fetch_early(deps, env, info, pool_id, id),
+        ExecuteMsg::EmergencyWithdraw { pool_id } => try_emergency_withdraw(deps, env, info, pool_id),
+        ExecuteMsg::FetchAllMatured { pool_id } => try_fetch_all_matured(deps, env, info, pool_id),
+        ExecuteMsg::AddStakers { pool_id, stakers } => execute_add_stakers(deps, env, info, pool_id, stakers),
+        ExecuteMsg::RemoveStaker { pool_id, address } => execute_remove_staker(deps, env, info, pool_id, address),
+        ExecuteMsg::RemoveAllStakers { pool_id, start_after, limit } => execute_remove_all_stakers(deps, env, info, pool_id, start_after, limit),
+    }
+}
+
+/// Reward per allocation point accumulated up to `now`, scaled by MULTIPLE.
+/// daily_reward_amount is emitted every reward_interval seconds until period_finish and split by alloc_point.
+pub fn reward_per_point(token: &RewardToken, total_alloc_point: u64, reward_interval: u64, now: u64) -> Uint256 {
+    let applicable = now.min(token.period_finish);
+    if total_alloc_point == 0 || reward_interval == 0 || applicable <= token.last_update_time {
+        return token.reward_per_point_stored;
+    }
+    let elapsed = (applicable - token.last_update_time) as u128;
+    token.reward_per_point_stored + Uint256::from(token.daily_reward_amount).multiply_ratio(
+        Uint256::from(elapsed) * Uint256::from(MULTIPLE),
+        total_alloc_point as u128 * reward_interval as u128
+    )
+}
+
+/// Brings a token accumulator up to `now`. Must be called before alloc points or the emission change.
+pub fn update_reward_per_point(token: &mut RewardToken, total_alloc_point: u64, reward_interval: u64, now: u64) {
+    token.reward_per_point_stored = reward_per_point(token, total_alloc_point, reward_interval, now);
+    let applicable = now.min(token.period_finish);
+    if applicable > token.last_update_time {
+        token.last_update_time = applicable;
+    }
+}
+
+/// Reward still to be emitted by the running program at the current rate
+pub fn remaining_reward(token: &RewardToken, reward_interval: u64, now: u64) -> Uint128 {
+    if now >= token.period_finish || reward_interval == 0 {
+        return Uint128::zero();
+    }
+    token.daily_reward_amount.multiply_ratio(token.period_finish - now, reward_interval)
+}
+
+/// Reward of a staker with boosted `weight` and checkpoint `reward_per_token_paid`
+pub fn earned(
+    weight: Uint128,
+    reward: Uint128,
+    reward_per_token_paid: Uint256,
+    reward_per_token: Uint256
+) -> StdResult<Uint128> {
+    Ok(reward + Uint128::try_from(Uint256::from(weight).multiply_ratio(reward_per_token - reward_per_token_paid, MULTIPLE))?)
+}
+
+/// Boosted weight of `amount` staked with the lock tier of `lock_days`
+fn lock_weight(cfg: &Config, amount: Uint128, lock_days: u64) -> Result<Uint128, ContractError> {
+    let tier = cfg.lock_tiers.iter()
+        .find(|t| t.days == lock_days)
+        .ok_or(ContractError::LockTierNotFound {})?;
+    Ok(amount.multiply_ratio(tier.multiplier, MULTIPLIER_BASE))
+}
+
+/// Penalty on fetching `amount` at `now` from an unbonding of `period` seconds unlocking at `unlock_time`
+pub fn early_penalty(cfg: &Config, amount: Uint128, unlock_time: u64, period: u64, now: u64) -> Uint128 {
+    if unlock_time <= now {
+        return Uint128::zero();
+    }
+    let penalty = amount.multiply_ratio(cfg.early_unstake_penalty.percent, 100u64);
+    if !cfg.early_unstake_penalty.decay || period == 0 {
+        return penalty;
+    }
+    penalty.multiply_ratio((unlock_time - now).min(period), period)
+}
+
+/// Unlocked stake always weighs its amount, so the 0-day tier must be 1x
+fn valid_lock_tiers(lock_tiers: &[LockTier]) -> bool {
+    lock_tiers.iter().any(|t| t.days == 0)
+        && lock_tiers.iter().all(|t| t.multiplier > 0 && (t.days > 0 || t.multiplier == 100))
+}
+
+/// Brings every reward token accumulator up to `now` and returns them
+pub fn update_reward_tokens(
+    storage: &mut dyn Storage,
+    cfg: &Config,
+    now: u64
+) -> StdResult<Vec<(String, RewardToken)>> {
+    let tokens = reward_tokens_at(storage, cfg, now)?;
+    for (token_key, token) in tokens.iter() {
+        REWARD_TOKENS.save(storage, token_key.clone(), token)?;
+    }
+    Ok(tokens)
+}
+
+/// Every reward token accumulator as it would be at `now`, nothing is saved
+pub fn reward_tokens_at(
+    storage: &dyn Storage,
+    cfg: &Config,
+    now: u64
+) -> StdResult<Vec<(String, RewardToken)>> {
+    let mut tokens = REWARD_TOKENS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (_, token) in tokens.iter_mut() {
+        update_reward_per_point(token, cfg.total_alloc_point, cfg.reward_interval, now);
+    }
+    Ok(tokens)
+}
+
+/// A pool brought up to some time by pool_rewards_at
+pub struct PoolRewards {
+    /// (reward token, reward per unit of weight, reward accrued since the last settlement)
+    pub rewards: Vec<(String, Uint256, Uint128)>,
+    /// (staker, lock_end, reward per unit of weight of every token at lock_end) of the locks
+    /// that ended since the last settlement, in lock_end order
+    pub expired: Vec<(Addr, u64, Vec<Uint256>)>,
+    /// total_weight once the boost of the expired locks is gone
+    pub total_weight: Uint128
+}
+
+/// Reward per allocation point of `token` at `time` between the pool settlement at `since`, when it
+/// was `paid`, and `now`. The emission rate only changes once every pool is settled, so it is linear in between.
+fn reward_per_point_between(token: &RewardToken, paid: Uint256, since: u64, now: u64, time: u64) -> Uint256 {
+    let start = since.min(token.period_finish);
+    let end = now.min(token.period_finish);
+    if end <= start {
+        return paid;
+    }
+    let elapsed = time.min(token.period_finish).max(start) - start;
+    paid + (token.reward_per_point_stored - paid).multiply_ratio(elapsed, end - start)
+}
+
+/// The pool under the accumulators of `tokens` at `now`. Locks that ended since the last settlement
+/// lose their boost at lock_end, nothing is saved.
+pub fn pool_rewards_at(
+    storage: &dyn Storage,
+    pool_id: u64,
+    pool: &Pool,
+    tokens: &[(String, RewardToken)],
+    now: u64
+) -> StdResult<PoolRewards> {
+    // (reward_per_token, reward_per_point reached, accrued) of every token
+    let mut cursors = vec![];
+    for (token_key, _) in tokens {
+        let (stored, paid) = POOL_REWARDS.may_load(storage, (pool_id, token_key.clone()))?.unwrap_or_default();
+        cursors.push((stored, paid, paid, Uint128::zero()));
+    }
+    let mut total_weight = pool.total_weight;
+    let advance = |cursors: &mut Vec<(Uint256, Uint256, Uint256, Uint128)>, total_weight: Uint128, time: u64| -> StdResult<()> {
+        for ((_, token), (reward_per_token, paid, reached, accrued)) in tokens.iter().zip(cursors.iter_mut()) {
+            let reward_per_point = reward_per_point_between(token, *paid, pool.last_update_time, now, time);
+            if !total_weight.is_zero() {
+                *reward_per_token += (reward_per_point - *reached).multiply_ratio(pool.alloc_point, total_weight);
+                *accrued += Uint128::try_from((reward_per_point - *reached).multiply_ratio(pool.alloc_point, MULTIPLE))?;
+            }
+            *reached = reward_per_point;
+        }
+        Ok(())
+    };
+
+    let mut expired = vec![];
+    for item in LOCK_EXPIRIES.sub_prefix(pool_id).range(storage, None, None, Order::Ascending) {
+        let ((lock_end, address), boost) = item?;
+        if lock_end > now {
+            break;
+        }
+        advance(&mut cursors, total_weight, lock_end)?;
+        expired.push((address, lock_end, cursors.iter().map(|(reward_per_token, _, _, _)| *reward_per_token).collect()));
+        total_weight -= boost;
+    }
+    advance(&mut cursors, total_weight, now)?;
+
+    Ok(PoolRewards {
+        rewards: tokens.iter().zip(cursors)
+            .map(|((token_key, _), (reward_per_token, _, _, accrued))| (token_key.clone(), reward_per_token, accrued))
+            .collect(),
+        expired,
+        total_weight
+    })
+}
+
+/// Settles the pool share of every reward token up to `now` and returns its reward per unit of weight.
+/// The settled share becomes owed to the stakers, unless nobody stakes in the pool. Stakers whose lock
+/// ended are settled at lock_end and back to their unboosted weight. Saves the pool.
+/// Must be called before the pool total_weight or alloc_point changes.
+pub fn update_pool(
+    storage: &mut dyn Storage,
+    pool_id: u64,
+    pool: &mut Pool,
+    tokens: &[(String, RewardToken)],
+    now: u64
+) -> StdResult<Vec<(String, Uint256)>> {
+    let pool_rewards = pool_rewards_at(storage, pool_id, pool, tokens, now)?;
+    for ((token_key, token), (_, reward_per_token, accrued)) in tokens.iter().zip(pool_rewards.rewards.iter()) {
+        POOL_REWARDS.save(storage, (pool_id, token_key.clone()), &(*reward_per_token, token.reward_per_point_stored))?;
+        if !accrued.is_zero() {
+            REWARD_TOKENS.update(storage, token_key.clone(), |t| -> StdResult<_> {
+                let mut t = t.unwrap_or_else(|| token.clone());
+                t.reward_owed += *accrued;
+                Ok(t)
+            })?;
+        }
+    }
+    for (address, lock_end, rewards_per_token) in pool_rewards.expired {
+        let mut staker = STAKERS.load(storage, (pool_id, address.clone()))?;
+        for ((token_key, _), reward_per_token) in tokens.iter().zip(rewards_per_token) {
+            let key = (pool_id, address.clone(), token_key.clone());
+            let (reward, reward_per_token_paid) = STAKER_REWARDS.may_load(storage, key.clone())?.unwrap_or_default();
+            STAKER_REWARDS.save(storage, key, &(earned(staker.weight, reward, reward_per_token_paid, reward_per_token)?, reward_per_token))?;
+        }
+        staker.penalty_reward = earned(staker.weight, staker.penalty_reward, staker.penalty_per_weight_paid, pool.penalty_per_weight)?;
+        staker.penalty_per_weight_paid = pool.penalty_per_weight;
+        staker.weight = staker.amount;
+        staker.lock_days = 0;
+        STAKERS.save(storage, (pool_id, address.clone()), &staker)?;
+        LOCK_EXPIRIES.remove(storage, (pool_id, lock_end, address));
+    }
+    pool.total_weight = pool_rewards.total_weight;
+    pool.last_update_time = now;
+    POOLS.save(storage, pool_id, pool)?;
+
+    Ok(pool_rewards.rewards.into_iter()
+        .map(|(token_key, reward_per_token, _)| (token_key, reward_per_token))
+        .collect())
+}
+
+/// Settles every reward token and pool up to `now`, needed before the emission per allocation point changes
+pub fn update_pools(
+    storage: &mut dyn Storage,
+    cfg: &Config,
+    now: u64
+) -> StdResult<Vec<(String, RewardToken)>> {
+    let tokens = update_reward_tokens(storage, cfg, now)?;
+    let pools = POOLS
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (pool_id, mut pool) in pools {
+        update_pool(storage, pool_id, &mut pool, &tokens, now)?;
+    }
+    Ok(tokens)
+}
+
+/// Reward of every token a staker could claim under `pool_rewards`, nothing is saved
+pub fn pending_rewards(
+    storage: &dyn Storage,
+    pool_id: u64,
+    address: &Addr,
+    staker: &Staker,
+    pool_rewards: &PoolRewards
+) -> StdResult<Vec<(String, Uint128)>> {
+    let expired = pool_rewards.expired.iter().find(|(expired, _, _)| expired == address);
+    pool_rewards.rewards.iter()
+        .enumerate()
+        .map(|(i, (token_key, reward_per_token, _))| {
+            let (mut reward, mut reward_per_token_paid) = STAKER_REWARDS
+                .may_load(storage, (pool_id, address.clone(), token_key.clone()))?
+                .unwrap_or_default();
+            let mut weight = staker.weight;
+            // the boost is only earned up to lock_end
+            if let Some((_, _, rewards_per_token)) = expired {
+                reward = earned(weight, reward, reward_per_token_paid, rewards_per_token[i])?;
+                reward_per_token_paid = rewards_per_token[i];
+                weight = staker.amount;
+            }
+            Ok((token_key.clone(), earned(weight, reward, reward_per_token_paid, *reward_per_token)?))
+        })
+        .collect()
+}
+
+/// Saves the staker along with its active stake at `height`
+fn save_staker(storage: &mut dyn Storage, height: u64, pool_id: u64, address: &Addr, staker: &Staker) -> StdResult<()> {
+    STAKED_AMOUNTS.save(storage, (pool_id, address.clone()), &staker.amount, height)?;
+    STAKERS.save(storage, (pool_id, address.clone()), staker)
+}
+
+/// Saves the pool along with its total active stake at `height`
+fn save_pool(storage: &mut dyn Storage, height: u64, pool_id: u64, pool: &Pool) -> StdResult<()> {
+    TOTAL_STAKED.save(storage, pool_id, &pool.total_active_stake, height)?;
+    POOLS.save(storage, pool_id, pool)
+}
+
+/// StakeChangedHookMsg to every registered hook, sent once UNSTAKING is up to date
+fn stake_changed_hooks(
+    storage: &dyn Storage,
+    pool_id: u64,
+    address: &Addr,
+    old: Uint128,
+    new: Uint128
+) -> StdResult<Vec<SubMsg>> {
+    let hooks = HOOKS.may_load(storage)?.unwrap_or_default();
+    if hooks.is_empty() {
+        return Ok(vec![]);
+    }
+    let unbonding = UNSTAKING
+        .prefix((pool_id, address.clone()))
+        .range(storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, (amount, _, _))| amount))
+        .sum::<StdResult<Uint128>>()?;
+    hooks.into_iter()
+        .map(|hook| {
+            let msg = StakeChangedHookMsg { pool_id, address: address.to_string(), old, new, unbonding };
+            Ok(SubMsg::new(msg.into_cosmos_msg(hook)?))
+        })
+        .collect()
+}
+
+/// Like stake_changed_hooks for stake leaving the contract, a failing hook can't hold up the withdrawal
+fn withdrawal_hooks(
+    storage: &dyn Storage,
+    pool_id: u64,
+    address: &Addr,
+    old: Uint128,
+    new: Uint128
+) -> StdResult<Vec<SubMsg>> {
+    Ok(stake_changed_hooks(storage, pool_id, address, old, new)?
+        .into_iter()
+        .map(|hook| SubMsg::reply_on_error(hook.msg, HOOK_REPLY_ID))
+        .collect())
+}
 
 pub fn update_reward (
     storage: &mut dyn Storage,
     env: Env,
-    address: Addr,
-    _start_after:Option<String>
+    pool_id: u64,
+    address: Addr
 ) -> Result<Response, ContractError> {
-    
-    let exists = STAKERS.may_load(storage, address.clone())?;
-    let (mut amount, mut reward, mut last_time) = (Uint128::zero(), Uint128::zero(), 0u64);
-    if exists.is_some() {
-        (amount, reward, last_time) = exists.unwrap();
+
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(storage)?;
+    let mut pool = POOLS.may_load(storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?;
+    let tokens = update_reward_tokens(storage, &cfg, now)?;
+    let pool_rewards = update_pool(storage, pool_id, &mut pool, &tokens, now)?;
+
+    let mut staker = STAKERS.may_load(storage, (pool_id, address.clone()))?.unwrap_or_default();
+    staker.penalty_reward = earned(staker.weight, staker.penalty_reward, staker.penalty_per_weight_paid, pool.penalty_per_weight)?;
+    staker.penalty_per_weight_paid = pool.penalty_per_weight;
+    for (token_key, reward_per_token) in pool_rewards {
+        let key = (pool_id, address.clone(), token_key);
+        let (reward, reward_per_token_paid) = STAKER_REWARDS.may_load(storage, key.clone())?.unwrap_or_default();
+        let reward = earned(staker.weight, reward, reward_per_token_paid, reward_per_token)?;
+        STAKER_REWARDS.save(storage, key, &(reward, reward_per_token))?;
     }
+    staker.last_time = now;
+    STAKERS.save(storage, (pool_id, address), &staker)?;
 
-    if last_time == 0u64 {
-        last_time = env.block.time.seconds();
+    Ok(Response::default())
+}
+
+pub fn transfer_msg(denom: &Denom, recipient: &Addr, amount: Uint128) -> StdResult<CosmosMsg> {
+    match denom {
+        Denom::Native(denom) => Ok(BankMsg::Send {
+            to_address: recipient.to_string(),
+            amount: coins(amount.u128(), denom),
+        }.into()),
+        Denom::Cw20(token) => Ok(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Transfer {
+                recipient: recipient.to_string(),
+                amount,
+            })?,
+            funds: vec![],
+        }.into()),
     }
+}
 
-    STAKERS.save(storage, address.clone(), &(amount, reward, last_time))?;
+pub fn burn_msg(denom: &Denom, amount: Uint128) -> StdResult<CosmosMsg> {
+    match denom {
+        Denom::Native(denom) => Ok(BankMsg::Burn {
+            amount: coins(amount.u128(), denom),
+        }.into()),
+        Denom::Cw20(token) => Ok(WasmMsg::Execute {
+            contract_addr: token.to_string(),
+            msg: to_json_binary(&Cw20ExecuteMsg::Burn { amount })?,
+            funds: vec![],
+        }.into()),
+    }
+}
 
-    let cfg = CONFIG.load(storage)?;
-    let delta = (env.block.time.seconds() + cfg.delta_time) / cfg.reward_interval - (last_time + cfg.delta_time) / cfg.reward_interval;
-    
-    if cfg.stake_amount > Uint128::zero() && amount > Uint128::zero() && delta > 0 {
-        reward += cfg.daily_reward_amount * Uint128::from(delta) * amount / cfg.stake_amount;
-        STAKERS.save(storage, address.clone(), &(amount, reward, env.block.time.seconds()))?;
+/// Balance of `address` in `denom`
+pub fn query_token_balance(querier: &QuerierWrapper, denom: &Denom, address: &Addr) -> StdResult<Uint128> {
+    match denom {
+        Denom::Native(denom) => Ok(querier.query_balance(address, denom)?.amount),
+        Denom::Cw20(token) => {
+            let balance: BalanceResponse = querier.query_wasm_smart(token, &Cw20QueryMsg::Balance {
+                address: address.to_string(),
+            })?;
+            Ok(balance.balance)
+        },
     }
+}
 
-    Ok(Response::default())
+/// Amount of `denom` the contract accounts for: stake and penalty of its pool, or the reward balance
+pub fn tracked_balance(storage: &dyn Storage, denom: &Denom) -> StdResult<Uint128> {
+    let token_key = denom_key(denom);
+    if let Some(pool_id) = POOL_IDS.may_load(storage, token_key.clone())? {
+        let pool = POOLS.load(storage, pool_id)?;
+        return Ok(pool.total_active_stake + pool.total_unbonding + pool.penalty_amount);
+    }
+    Ok(REWARD_TOKENS.may_load(storage, token_key)?
+        .map(|token| token.reward_amount)
+        .unwrap_or_default())
+}
+
+pub fn validate_denom(api: &dyn Api, denom: Denom) -> StdResult<Denom> {
+    match denom {
+        Denom::Native(denom) => {
+            if denom.is_empty() {
+                return Err(StdError::generic_err("Empty native denom"));
+            }
+            Ok(Denom::Native(denom))
+        },
+        Denom::Cw20(address) => Ok(Denom::Cw20(api.addr_validate(address.as_str())?)),
+    }
 }
 
 pub fn try_receive(
-    deps: DepsMut, 
+    deps: DepsMut,
     env: Env,
-    info: MessageInfo, 
+    info: MessageInfo,
     wrapper: Cw20ReceiveMsg
 ) -> Result<Response, ContractError> {
-    
-    check_enabled(&deps, &info)?;
-    let mut cfg = CONFIG.load(deps.storage)?;
-    
-    if wrapper.amount == Uint128::zero() {
+
+    let user_addr = deps.api.addr_validate(&wrapper.sender)?;
+    let msg = from_json(&wrapper.msg)?;
+    try_deposit(deps, env, Denom::Cw20(info.sender), user_addr, wrapper.amount, msg)
+}
+
+pub fn try_receive_native(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    msg: ReceiveMsg
+) -> Result<Response, ContractError> {
+
+    let coin = one_coin(&info)?;
+    try_deposit(deps, env, Denom::Native(coin.denom), info.sender, coin.amount, msg)
+}
+
+/// Routes a cw20 or native deposit of `denom` to the pool or reward program it belongs to
+pub fn try_deposit(
+    deps: DepsMut,
+    env: Env,
+    denom: Denom,
+    user_addr: Addr,
+    amount: Uint128,
+    msg: ReceiveMsg
+) -> Result<Response, ContractError> {
+
+    let cfg = CONFIG.load(deps.storage)?;
+    if !cfg.enabled {
+        return Err(ContractError::Disabled {});
+    }
+    let token_key = denom_key(&denom);
+    let pool_id = POOL_IDS.may_load(deps.storage, token_key.clone())?;
+    let is_reward_token = REWARD_TOKENS.has(deps.storage, token_key.clone());
+
+    if pool_id.is_none() && !is_reward_token {
+        return Err(ContractError::UnacceptableToken {});
+    }
+    if amount == Uint128::zero() {
         return Err(ContractError::InvalidInput {});
     }
-    let user_addr = &deps.api.addr_validate(&wrapper.sender)?;
-
-    // Staking case
-    if info.sender == cfg.stake_token_address {
-        update_reward(deps.storage,  env, user_addr.clone(), None)?;
-        let (mut amount, reward, last_time) = STAKERS.load(deps.storage, user_addr.clone())?;
-        amount += wrapper.amount;
-        STAKERS.save(deps.storage, user_addr.clone(), &(amount, reward, last_time))?;
-        
-        cfg.stake_amount = cfg.stake_amount + wrapper.amount;
-        CONFIG.save(deps.storage, &cfg)?;
-
-        return Ok(Response::new()
-            .add_attributes(vec![
-                attr("action", "stake"),
-                attr("address", user_addr),
-                attr("amount", wrapper.amount)
-            ]));
-
-    } else if info.sender == cfg.reward_token_address {
-        //Just receive in contract cache and update config
-        cfg.reward_amount = cfg.reward_amount + wrapper.amount;
-        CONFIG.save(deps.storage, &cfg)?;
-
-        return Ok(Response::new()
-            .add_attributes(vec![
-                attr("action", "fund"),
-                attr("address", user_addr),
-                attr("amount", wrapper.amount),
-            ]));
 
-    } else {
-        return Err(ContractError::UnacceptableToken {})
+    match msg {
+        ReceiveMsg::Stake { lock_days } => {
+            let pool_id = pool_id.ok_or(ContractError::TokenMismatch {})?;
+            try_stake(deps, env, pool_id, user_addr, amount, Some(lock_days.unwrap_or_default()))
+        },
+        ReceiveMsg::StakeFor { recipient } => {
+            let pool_id = pool_id.ok_or(ContractError::TokenMismatch {})?;
+            let recipient = deps.api.addr_validate(&recipient)?;
+            // never lock or extend someone else's position
+            Ok(try_stake(deps, env, pool_id, recipient, amount, None)?
+                .add_attribute("sender", user_addr))
+        },
+        ReceiveMsg::InitialFund { duration } => {
+            if !is_reward_token {
+                return Err(ContractError::TokenMismatch {});
+            }
+            if !has_role(deps.storage, &user_addr, Role::Funder)? {
+                return Err(ContractError::Unauthorized {});
+            }
+            try_initial_fund(deps, env, token_key, user_addr, amount, duration)
+        },
+    }
+}
+
+/// Stakes with the tier of `lock_days`, or joins the running lock of the position as it is with None
+pub fn try_stake(
+    deps: DepsMut,
+    env: Env,
+    pool_id: u64,
+    user_addr: Addr,
+    stake_amount: Uint128,
+    lock_days: Option<u64>
+) -> Result<Response, ContractError> {
+
+    let cfg = CONFIG.load(deps.storage)?;
+    if POOLS.load(deps.storage, pool_id)?.retired {
+        return Err(ContractError::PoolRetired {});
+    }
+
+    let now = env.block.time.seconds();
+    let height = env.block.height;
+    update_reward(deps.storage, env, pool_id, user_addr.clone())?;
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+    let mut staker = STAKERS.load(deps.storage, (pool_id, user_addr.clone()))?;
+    let old = staker.amount;
+    LOCK_EXPIRIES.remove(deps.storage, (pool_id, staker.lock_end, user_addr.clone()));
+    match lock_days {
+        Some(lock_days) => {
+            if staker.lock_end > now && staker.lock_days != lock_days {
+                return Err(ContractError::MixedLockTiers {});
+            }
+            // the whole position is locked again on the tier, so all of it carries the boost
+            staker.amount += stake_amount;
+            let weight = lock_weight(&cfg, staker.amount, lock_days)?;
+            pool.total_weight = pool.total_weight - staker.weight + weight;
+            staker.weight = weight;
+            staker.lock_days = lock_days;
+            staker.lock_end = now + lock_days * 86400u64;
+        },
+        None => {
+            // the stake takes the multiplier of the running lock and ends with it
+            let weight = if staker.lock_end > now {
+                staker.weight.multiply_ratio(stake_amount, staker.amount)
+            } else {
+                stake_amount
+            };
+            staker.amount += stake_amount;
+            staker.weight += weight;
+            pool.total_weight += weight;
+        },
+    }
+    if staker.weight > staker.amount {
+        LOCK_EXPIRIES.save(deps.storage, (pool_id, staker.lock_end, user_addr.clone()), &(staker.weight - staker.amount))?;
+    }
+    save_staker(deps.storage, height, pool_id, &user_addr, &staker)?;
+
+    pool.total_active_stake += stake_amount;
+    save_pool(deps.storage, height, pool_id, &pool)?;
+
+    Ok(Response::new()
+        .add_submessages(stake_changed_hooks(deps.storage, pool_id, &user_addr, old, staker.amount)?)
+        .add_attributes(vec![
+            attr("action", "stake"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", user_addr),
+            attr("amount", stake_amount),
+            attr("weight", staker.weight),
+            attr("lock_end", staker.lock_end.to_string())
+        ]))
+}
+
+pub fn try_initial_fund(
+    deps: DepsMut,
+    env: Env,
+    token_key: String,
+    funder: Addr,
+    amount: Uint128,
+    duration: u64
+) -> Result<Response, ContractError> {
+
+    if duration == 0 {
+        return Err(ContractError::InvalidInput {});
     }
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(deps.storage)?;
+    // every pool is settled under the old rate before it changes
+    update_pools(deps.storage, &cfg, now)?;
+    let mut token = REWARD_TOKENS.load(deps.storage, token_key.clone())?;
+
+    // the new rate emits exactly the deposit plus whatever the running program had left
+    let total = amount + remaining_reward(&token, cfg.reward_interval, now);
+    token.daily_reward_amount = total.multiply_ratio(cfg.reward_interval, duration);
+    token.reward_amount += amount;
+    token.last_update_time = now;
+    token.period_finish = now + duration;
+    REWARD_TOKENS.save(deps.storage, token_key.clone(), &token)?;
+
+    Ok(Response::new()
+        .add_attributes(vec![
+            attr("action", "fund"),
+            attr("address", funder),
+            attr("reward_token", token_key),
+            attr("amount", amount),
+            attr("daily_reward_amount", token.daily_reward_amount),
+            attr("period_finish", token.period_finish.to_string()),
+        ]))
 }
 
 pub fn try_claim_reward(
     deps: DepsMut,
     env: Env,
-    info: MessageInfo
+    info: MessageInfo,
+    pool_id: u64
 ) -> Result<Response, ContractError> {
 
     check_enabled(&deps, &info)?;
-    update_reward(deps.storage, env.clone(), info.sender.clone(), None)?;
-    let mut cfg = CONFIG.load(deps.storage)?;
+    update_reward(deps.storage, env, pool_id, info.sender.clone())?;
+
+    let rewards = STAKER_REWARDS
+        .prefix((pool_id, info.sender.clone()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut attrs = vec![
+        attr("action", "claim_reward"),
+        attr("pool_id", pool_id.to_string()),
+        attr("address", info.sender.clone()),
+    ];
+    for (token_key, (reward, reward_per_token_paid)) in rewards {
+        if reward == Uint128::zero() {
+            continue;
+        }
+        let mut token = REWARD_TOKENS.load(deps.storage, token_key.clone())?;
+        if token.reward_amount < reward {
+            return Err(ContractError::NotEnoughReward {});
+        }
+        token.reward_amount -= reward;
+        token.reward_owed -= reward.min(token.reward_owed);
+        REWARD_TOKENS.save(deps.storage, token_key.clone(), &token)?;
+        STAKER_REWARDS.save(deps.storage, (pool_id, info.sender.clone(), token_key.clone()), &(Uint128::zero(), reward_per_token_paid))?;
+
+        messages.push(transfer_msg(&token.denom, &info.sender, reward)?);
+        attrs.push(attr("reward_token", token_key));
+        attrs.push(attr("reward_amount", reward));
+    }
 
-    let (amount, reward, last_time) = STAKERS.load(deps.storage, info.sender.clone())?;
-    
-    if reward == Uint128::zero() {
-        return Err(ContractError::NoReward {});
+    let mut staker = STAKERS.load(deps.storage, (pool_id, info.sender.clone()))?;
+    if !staker.penalty_reward.is_zero() {
+        let mut pool = POOLS.load(deps.storage, pool_id)?;
+        let penalty_reward = staker.penalty_reward.min(pool.penalty_amount);
+        pool.penalty_amount -= penalty_reward;
+        POOLS.save(deps.storage, pool_id, &pool)?;
+        staker.penalty_reward = Uint128::zero();
+        STAKERS.save(deps.storage, (pool_id, info.sender.clone()), &staker)?;
+
+        messages.push(transfer_msg(&pool.stake_token, &info.sender, penalty_reward)?);
+        attrs.push(attr("penalty_reward", penalty_reward));
     }
-    if cfg.reward_amount < reward {
-        return Err(ContractError::NotEnoughReward {});
+
+    if messages.is_empty() {
+        return Err(ContractError::NoReward {});
     }
-    
-    cfg.reward_amount -= Uint128::from(reward);
-    CONFIG.save(deps.storage, &cfg)?;
-    
-    // if amount == Uint128::zero() {
-        // STAKERS.remove(deps.storage, info.sender.clone());
-    // } else {
-        STAKERS.save(deps.storage, info.sender.clone(), &(amount, Uint128::zero(), last_time))?;
-    // }
-
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: cfg.reward_token_address.clone().into(),
-        msg: to_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: info.sender.clone().into(),
-            amount: Uint128::from(reward),
-        })?,
-        funds: vec![],
-    };
 
-    
-    return Ok(Response::new()
-        .add_message(exec_cw20_transfer)
-        .add_attributes(vec![
-            attr("action", "claim_reward"),
-            attr("address", info.sender.clone()),
-            attr("reward_amount", Uint128::from(reward)),
-        ]));
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attrs))
 }
 
 pub fn try_create_unstake(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
+    pool_id: u64,
     unstake_amount: Uint128
 ) -> Result<Response, ContractError> {
 
     check_enabled(&deps, &info)?;
-    update_reward(deps.storage, env.clone(), info.sender.clone(), None)?;
-    let mut cfg = CONFIG.load(deps.storage)?;
-    let (amount, reward, last_time) = STAKERS.load(deps.storage, info.sender.clone())?;
-    
+    update_reward(deps.storage, env.clone(), pool_id, info.sender.clone())?;
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+    let mut staker = STAKERS.load(deps.storage, (pool_id, info.sender.clone()))?;
+
     if unstake_amount == Uint128::zero() {
         return Err(ContractError::InvalidInput {});
     }
+    if staker.lock_end > env.block.time.seconds() {
+        return Err(ContractError::StillInLock {});
+    }
 
+    let amount = staker.amount;
     if amount < unstake_amount {
         return Err(ContractError::NotEnoughStake {});
     }
-    if cfg.stake_amount < unstake_amount {
-        return Err(ContractError::NotEnoughStake {});
+    if pool.total_active_stake < unstake_amount {
+        return Err(ContractError::NotEnoughStake {});
+    }
+
+    let id = UNSTAKE_COUNT.may_load(deps.storage)?.unwrap_or_default();
+    UNSTAKE_COUNT.save(deps.storage, &(id + 1))?;
+    let period = cfg.lock_days * 86400u64;
+    UNSTAKING.save(deps.storage, (pool_id, info.sender.clone(), id), &(unstake_amount, env.block.time.seconds() + period, period))?;
+
+    // unbonding stake stops earning, the boost leaves with it pro rata
+    let weight = staker.weight.multiply_ratio(unstake_amount, amount);
+    staker.amount -= unstake_amount;
+    staker.weight -= weight;
+    save_staker(deps.storage, env.block.height, pool_id, &info.sender, &staker)?;
+
+    pool.total_weight -= weight;
+    pool.total_active_stake -= unstake_amount;
+    pool.total_unbonding += unstake_amount;
+    save_pool(deps.storage, env.block.height, pool_id, &pool)?;
+
+    Ok(Response::new()
+        .add_submessages(stake_changed_hooks(deps.storage, pool_id, &info.sender, amount, staker.amount)?)
+        .add_attributes(vec![
+            attr("action", "create_unstake"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("stake_amount", amount),
+            attr("unstake_id", id.to_string()),
+        ]))
+}
+
+
+pub fn try_fetch_unstake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: u64,
+    id: u64
+) -> Result<Response, ContractError> {
+
+    check_enabled(&deps, &info)?;
+    update_reward(deps.storage, env.clone(), pool_id, info.sender.clone())?;
+
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+
+    let (amount, timestamp, _) = UNSTAKING.may_load(deps.storage, (pool_id, info.sender.clone(), id))?
+        .ok_or(ContractError::NotCreatedUnstaking {})?;
+
+    if pool.total_unbonding < amount {
+        return Err(ContractError::NotEnoughStake {});
+    }
+    if timestamp > env.block.time.seconds() {
+        return Err(ContractError::StillLocked {});
+    }
+    pool.total_unbonding -= amount;
+    POOLS.save(deps.storage, pool_id, &pool)?;
+
+    UNSTAKING.remove(deps.storage, (pool_id, info.sender.clone(), id));
+
+    let stake_amount = STAKERS.may_load(deps.storage, (pool_id, info.sender.clone()))?.unwrap_or_default().amount;
+    Ok(Response::new()
+        .add_message(transfer_msg(&pool.stake_token, &info.sender, amount)?)
+        .add_submessages(withdrawal_hooks(deps.storage, pool_id, &info.sender, stake_amount, stake_amount)?)
+        .add_attributes(vec![
+            attr("action", "fetch_unstake"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("unstake_id", id.to_string()),
+            attr("stake_amount", amount),
+        ]))
+}
+
+pub fn try_cancel_unstake(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: u64,
+    id: u64,
+    amount: Option<Uint128>
+) -> Result<Response, ContractError> {
+
+    check_enabled(&deps, &info)?;
+    let key = (pool_id, info.sender.clone(), id);
+    let (unstake_amount, timestamp, period) = UNSTAKING.may_load(deps.storage, key.clone())?
+        .ok_or(ContractError::NotCreatedUnstaking {})?;
+    let amount = amount.unwrap_or(unstake_amount);
+    if amount == Uint128::zero() {
+        return Err(ContractError::InvalidInput {});
+    }
+    if amount > unstake_amount {
+        return Err(ContractError::NotEnoughStake {});
+    }
+
+    let cfg = CONFIG.load(deps.storage)?;
+    let weight = lock_weight(&cfg, amount, 0u64)?;
+    if POOLS.load(deps.storage, pool_id)?.retired {
+        return Err(ContractError::PoolRetired {});
+    }
+
+    // settle reward on the current weight before the restaked amount starts earning
+    let now = env.block.time.seconds();
+    let height = env.block.height;
+    update_reward(deps.storage, env, pool_id, info.sender.clone())?;
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+    let mut staker = STAKERS.load(deps.storage, (pool_id, info.sender.clone()))?;
+    // the amount comes back unlocked, it can't join a locked position
+    if staker.lock_end > now {
+        return Err(ContractError::StillInLock {});
+    }
+    let old = staker.amount;
+    staker.amount += amount;
+    staker.weight += weight;
+    save_staker(deps.storage, height, pool_id, &info.sender, &staker)?;
+
+    pool.total_weight += weight;
+    pool.total_unbonding -= amount;
+    pool.total_active_stake += amount;
+    save_pool(deps.storage, height, pool_id, &pool)?;
+
+    if amount == unstake_amount {
+        UNSTAKING.remove(deps.storage, key);
+    } else {
+        UNSTAKING.save(deps.storage, key, &(unstake_amount - amount, timestamp, period))?;
+    }
+
+    Ok(Response::new()
+        .add_submessages(stake_changed_hooks(deps.storage, pool_id, &info.sender, old, staker.amount)?)
+        .add_attributes(vec![
+            attr("action", "cancel_unstake"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("unstake_id", id.to_string()),
+            attr("amount", amount),
+        ]))
+}
+
+pub fn try_fetch_early(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: u64,
+    id: u64
+) -> Result<Response, ContractError> {
+
+    check_enabled(&deps, &info)?;
+    update_reward(deps.storage, env.clone(), pool_id, info.sender.clone())?;
+
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+
+    if cfg.early_unstake_penalty.percent >= 100 {
+        return Err(ContractError::EarlyExitClosed {});
+    }
+    let (amount, timestamp, period) = UNSTAKING.may_load(deps.storage, (pool_id, info.sender.clone(), id))?
+        .ok_or(ContractError::NotCreatedUnstaking {})?;
+
+    if pool.total_unbonding < amount {
+        return Err(ContractError::NotEnoughStake {});
+    }
+    pool.total_unbonding -= amount;
+    UNSTAKING.remove(deps.storage, (pool_id, info.sender.clone(), id));
+
+    let penalty = early_penalty(&cfg, amount, timestamp, period, env.block.time.seconds());
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if penalty < amount {
+        messages.push(transfer_msg(&pool.stake_token, &info.sender, amount - penalty)?);
+    }
+    if !penalty.is_zero() {
+        match &cfg.early_unstake_penalty.destination {
+            PenaltyDestination::Treasury(treasury) => messages.push(transfer_msg(&pool.stake_token, treasury, penalty)?),
+            PenaltyDestination::Burn => messages.push(burn_msg(&pool.stake_token, penalty)?),
+            // without weight left in the pool the penalty simply stays in the contract
+            PenaltyDestination::Redistribute => if !pool.total_weight.is_zero() {
+                pool.penalty_per_weight += Uint256::from(penalty).multiply_ratio(MULTIPLE, pool.total_weight);
+                pool.penalty_amount += penalty;
+            },
+        }
+    }
+    POOLS.save(deps.storage, pool_id, &pool)?;
+
+    let stake_amount = STAKERS.may_load(deps.storage, (pool_id, info.sender.clone()))?.unwrap_or_default().amount;
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_submessages(withdrawal_hooks(deps.storage, pool_id, &info.sender, stake_amount, stake_amount)?)
+        .add_attributes(vec![
+            attr("action", "fetch_early"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("unstake_id", id.to_string()),
+            attr("stake_amount", amount - penalty),
+            attr("penalty", penalty),
+        ]))
+}
+
+pub fn try_emergency_withdraw(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: u64
+) -> Result<Response, ContractError> {
+
+    // pausing must never hold stake, while enabled locks and early unstake penalties apply
+    let cfg = CONFIG.load(deps.storage)?;
+    if cfg.enabled {
+        return Err(ContractError::NotDisabled {});
+    }
+    let mut pool = POOLS.may_load(deps.storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?;
+    // settle the pool under the current weight so the other stakers keep their share
+    let tokens = update_reward_tokens(deps.storage, &cfg, env.block.time.seconds())?;
+    let pool_rewards = update_pool(deps.storage, pool_id, &mut pool, &tokens, env.block.time.seconds())?;
+
+    let (stake_amount, unbonding) = remove_staker(deps.storage, env.block.height, pool_id, &mut pool, &pool_rewards, info.sender.clone())?;
+    let amount = stake_amount + unbonding;
+    if amount.is_zero() {
+        return Err(ContractError::NoStaked {});
+    }
+    save_pool(deps.storage, env.block.height, pool_id, &pool)?;
+
+    Ok(Response::new()
+        .add_message(transfer_msg(&pool.stake_token, &info.sender, amount)?)
+        .add_submessages(withdrawal_hooks(deps.storage, pool_id, &info.sender, stake_amount, Uint128::zero())?)
+        .add_attributes(vec![
+            attr("action", "emergency_withdraw"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("stake_amount", stake_amount),
+            attr("unbonding_amount", unbonding),
+        ]))
+}
+
+pub fn try_fetch_all_matured(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    pool_id: u64
+) -> Result<Response, ContractError> {
+
+    check_enabled(&deps, &info)?;
+    update_reward(deps.storage, env.clone(), pool_id, info.sender.clone())?;
+
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
+    let now = env.block.time.seconds();
+
+    let list = UNSTAKING
+        .prefix((pool_id, info.sender.clone()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    if list.is_empty() {
+        return Err(ContractError::NotCreatedUnstaking {});
+    }
+
+    let mut amount = Uint128::zero();
+    let mut paid = 0u64;
+    for (id, (unstake_amount, timestamp, _)) in list.iter() {
+        if *timestamp > now {
+            continue;
+        }
+        amount += *unstake_amount;
+        paid += 1;
+        UNSTAKING.remove(deps.storage, (pool_id, info.sender.clone(), *id));
+    }
+    if paid == 0 {
+        return Err(ContractError::StillLocked {});
+    }
+
+    if pool.total_unbonding < amount {
+        return Err(ContractError::NotEnoughStake {});
+    }
+    pool.total_unbonding -= amount;
+    POOLS.save(deps.storage, pool_id, &pool)?;
+
+    let stake_amount = STAKERS.may_load(deps.storage, (pool_id, info.sender.clone()))?.unwrap_or_default().amount;
+    Ok(Response::new()
+        .add_message(transfer_msg(&pool.stake_token, &info.sender, amount)?)
+        .add_submessages(withdrawal_hooks(deps.storage, pool_id, &info.sender, stake_amount, stake_amount)?)
+        .add_attributes(vec![
+            attr("action", "fetch_all_matured"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", info.sender.clone()),
+            attr("stake_amount", amount),
+            attr("paid_count", paid.to_string()),
+            attr("locked_count", (list.len() as u64 - paid).to_string()),
+        ]))
+}
+
+pub fn check_owner(
+    deps: &DepsMut,
+    info: &MessageInfo
+) -> Result<Response, ContractError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let owner = cfg.owner.ok_or(ContractError::Unauthorized {})?;
+    if info.sender != owner {
+        return Err(ContractError::Unauthorized {})
+    }
+    Ok(Response::new().add_attribute("action", "check_owner"))
+}
+/// Whether `address` is the owner, an admin or holds `role`
+pub fn has_role(storage: &dyn Storage, address: &Addr, role: Role) -> StdResult<bool> {
+    let cfg = CONFIG.load(storage)?;
+    // roles are granted under the owner and are frozen with it once ownership is renounced
+    let owner = match cfg.owner {
+        Some(owner) => owner,
+        None => return Ok(false),
+    };
+    if owner == address {
+        return Ok(true);
+    }
+    let roles = ROLES.may_load(storage, address.clone())?.unwrap_or_default();
+    Ok(roles.contains(&Role::Admin) || roles.contains(&role))
+}
+
+pub fn check_role(
+    deps: &DepsMut,
+    info: &MessageInfo,
+    role: Role
+) -> Result<Response, ContractError> {
+    if !has_role(deps.storage, &info.sender, role)? {
+        return Err(ContractError::Unauthorized {})
+    }
+    Ok(Response::new().add_attribute("action", "check_role"))
+}
+pub fn check_enabled(
+    deps: &DepsMut,
+    _info: &MessageInfo
+) -> Result<Response, ContractError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    if !cfg.enabled {
+        return Err(ContractError::Disabled {})
+    }
+    Ok(Response::new().add_attribute("action", "check_enabled"))
+}
+
+pub fn execute_propose_owner(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    new_owner: String,
+    expiry: Option<Expiration>
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(&deps, &info)?;
+
+    let new_owner = deps.api.addr_validate(&new_owner)?;
+    let expiry = expiry.unwrap_or_default();
+    if expiry.is_expired(&env.block) {
+        return Err(ContractError::InvalidInput {});
+    }
+    PENDING_OWNER.save(deps.storage, &(new_owner.clone(), expiry))?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "propose_owner"),
+        attr("pending_owner", new_owner),
+        attr("expiry", expiry.to_string()),
+    ]))
+}
+
+pub fn execute_accept_ownership(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    let (pending_owner, expiry) = PENDING_OWNER.may_load(deps.storage)?
+        .ok_or(ContractError::NoPendingOwner {})?;
+    if info.sender != pending_owner {
+        return Err(ContractError::Unauthorized {});
+    }
+    if expiry.is_expired(&env.block) {
+        return Err(ContractError::OwnershipProposalExpired {});
+    }
+
+    PENDING_OWNER.remove(deps.storage);
+    CONFIG.update(deps.storage, |mut exists| -> StdResult<_> {
+        exists.owner = Some(pending_owner.clone());
+        Ok(exists)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "accept_ownership"),
+        attr("owner", pending_owner),
+    ]))
+}
+
+pub fn execute_cancel_ownership_proposal(
+    deps: DepsMut,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(&deps, &info)?;
+
+    if PENDING_OWNER.may_load(deps.storage)?.is_none() {
+        return Err(ContractError::NoPendingOwner {});
+    }
+    PENDING_OWNER.remove(deps.storage);
+
+    Ok(Response::new().add_attribute("action", "cancel_ownership_proposal"))
+}
+
+pub fn execute_renounce_ownership(
+    deps: DepsMut,
+    info: MessageInfo
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(&deps, &info)?;
+
+    PENDING_OWNER.remove(deps.storage);
+    CONFIG.update(deps.storage, |mut exists| -> StdResult<_> {
+        exists.owner = None;
+        Ok(exists)
+    })?;
+
+    Ok(Response::new().add_attribute("action", "renounce_ownership"))
+}
+
+pub fn execute_set_role(
+    deps: DepsMut,
+    info: MessageInfo,
+    address: String,
+    role: Role,
+    grant: bool
+) -> Result<Response, ContractError> {
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
+
+    let address = deps.api.addr_validate(&address)?;
+    let mut roles = ROLES.may_load(deps.storage, address.clone())?.unwrap_or_default();
+    roles.retain(|r| *r != role);
+    if grant {
+        roles.push(role);
+    }
+    if roles.is_empty() {
+        ROLES.remove(deps.storage, address.clone());
+    } else {
+        ROLES.save(deps.storage, address.clone(), &roles)?;
+    }
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", if grant { "grant_role" } else { "revoke_role" }),
+        attr("address", address),
+        attr("role", format!("{:?}", role)),
+    ]))
+}
+
+pub fn execute_set_enabled(
+    deps: DepsMut,
+    info: MessageInfo,
+    enabled: bool
+) -> Result<Response, ContractError> {
+    // authorize operator
+    check_role(&deps, &info, Role::Operator)?;
+
+    CONFIG.update(deps.storage, |mut exists| -> StdResult<_> {
+        exists.enabled = enabled;
+        Ok(exists)
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_enabled"),
+        attr("enabled", enabled.to_string()),
+    ]))
+}
+
+/// Role that queues, executes and cancels a change of `kind`
+fn change_role(kind: &ChangeKind) -> Role {
+    match kind {
+        ChangeKind::RewardToken { .. } => Role::Operator,
+        _ => Role::Admin,
+    }
+}
+
+/// Puts a change behind the timelock, `eta` must be at least min_delay from now
+fn queue_change(
+    deps: DepsMut,
+    env: Env,
+    kind: ChangeKind,
+    eta: u64,
+    action: &str
+) -> Result<Response, ContractError> {
+    let cfg = CONFIG.load(deps.storage)?;
+    if eta < env.block.time.seconds() + cfg.min_delay {
+        return Err(ContractError::EtaTooSoon { min_delay: cfg.min_delay });
+    }
+
+    let id = CONSTANTS_CHANGE_COUNT.may_load(deps.storage)?.unwrap_or_default();
+    CONSTANTS_CHANGE_COUNT.save(deps.storage, &(id + 1))?;
+    CONSTANTS_CHANGES.save(deps.storage, id, &ConstantsChange { kind, eta })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", action),
+        attr("change_id", id.to_string()),
+        attr("eta", eta.to_string()),
+    ]))
+}
+
+pub fn execute_update_constants(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    kind: ChangeKind,
+    eta: u64
+) -> Result<Response, ContractError> {
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
+
+    if let ChangeKind::Constants { early_unstake_penalty, .. } = &kind {
+        if early_unstake_penalty.percent > 100 {
+            return Err(ContractError::InvalidInput {});
+        }
+        if let PenaltyDestination::Treasury(treasury) = &early_unstake_penalty.destination {
+            deps.api.addr_validate(treasury.as_str())?;
+        }
+    }
+    queue_change(deps, env, kind, eta, "update_constants")
+}
+
+pub fn execute_constants_change(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    id: u64
+) -> Result<Response, ContractError> {
+    let change = CONSTANTS_CHANGES.may_load(deps.storage, id)?
+        .ok_or(ContractError::ConstantsChangeNotFound {})?;
+    // authorize the role that queued it
+    check_role(&deps, &info, change_role(&change.kind))?;
+
+    let now = env.block.time.seconds();
+    if now < change.eta {
+        return Err(ContractError::TimelockNotExpired {});
+    }
+
+    let mut cfg = CONFIG.load(deps.storage)?;
+    // settle rewards accrued under the old emission before changing it
+    let tokens = update_pools(deps.storage, &cfg, now)?;
+
+    match change.kind {
+        ChangeKind::Constants { reward_interval, delta_time, lock_days, early_unstake_penalty, min_delay } => {
+            // reward_interval scales every emission, the running programs must stay funded
+            for (_, token) in tokens.iter() {
+                if remaining_reward(token, reward_interval, now) > remaining_reward(token, cfg.reward_interval, now) {
+                    return Err(ContractError::NotEnoughReward {});
+                }
+            }
+            cfg.reward_interval = reward_interval;
+            cfg.delta_time = delta_time;
+            cfg.lock_days = lock_days;
+            cfg.early_unstake_penalty = early_unstake_penalty;
+            cfg.min_delay = min_delay;
+            CONFIG.save(deps.storage, &cfg)?;
+        },
+        ChangeKind::LockTiers { lock_tiers } => {
+            cfg.lock_tiers = lock_tiers;
+            CONFIG.save(deps.storage, &cfg)?;
+        },
+        ChangeKind::RewardToken { token, daily_reward_amount, apy_prefix } => {
+            let token_key = denom_key(&token);
+            let mut token = REWARD_TOKENS.may_load(deps.storage, token_key.clone())?
+                .ok_or(ContractError::UnacceptableToken {})?;
+            let committed = remaining_reward(&token, cfg.reward_interval, now);
+            token.daily_reward_amount = daily_reward_amount;
+            token.apy_prefix = apy_prefix;
+            // the running program can be slowed down but never promise more than it was funded with
+            if remaining_reward(&token, cfg.reward_interval, now) > committed {
+                return Err(ContractError::NotEnoughReward {});
+            }
+            REWARD_TOKENS.save(deps.storage, token_key, &token)?;
+        },
+    }
+    CONSTANTS_CHANGES.remove(deps.storage, id);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "execute_constants_change"),
+        attr("change_id", id.to_string()),
+    ]))
+}
+
+pub fn execute_cancel_constants_change(
+    deps: DepsMut,
+    info: MessageInfo,
+    id: u64
+) -> Result<Response, ContractError> {
+    let change = CONSTANTS_CHANGES.may_load(deps.storage, id)?
+        .ok_or(ContractError::ConstantsChangeNotFound {})?;
+    // authorize the role that queued it
+    check_role(&deps, &info, change_role(&change.kind))?;
+
+    CONSTANTS_CHANGES.remove(deps.storage, id);
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "cancel_constants_change"),
+        attr("change_id", id.to_string()),
+    ]))
+}
+
+pub fn execute_update_lock_tiers(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    lock_tiers: Vec<LockTier>,
+    eta: u64
+) -> Result<Response, ContractError> {
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
+
+    if !valid_lock_tiers(&lock_tiers) {
+        return Err(ContractError::InvalidInput {});
+    }
+    queue_change(deps, env, ChangeKind::LockTiers { lock_tiers }, eta, "update_lock_tiers")
+}
+
+pub fn execute_add_reward_token(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: Denom,
+    apy_prefix: Uint128
+) -> Result<Response, ContractError> {
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
+
+    let token = validate_denom(deps.api, token)?;
+    let token_key = denom_key(&token);
+    if POOL_IDS.has(deps.storage, token_key.clone()) {
+        return Err(ContractError::InvalidInput {});
+    }
+    if REWARD_TOKENS.has(deps.storage, token_key.clone()) {
+        return Err(ContractError::RewardTokenExists {});
+    }
+
+    // nothing is emitted until the token is funded with InitialFund
+    REWARD_TOKENS.save(deps.storage, token_key.clone(), &RewardToken {
+        denom: token,
+        reward_amount: Uint128::zero(),
+        reward_owed: Uint128::zero(),
+        daily_reward_amount: Uint128::zero(),
+        apy_prefix,
+        reward_per_point_stored: Uint256::zero(),
+        last_update_time: env.block.time.seconds(),
+        period_finish: 0u64
+    })?;
+
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "add_reward_token"),
+        attr("reward_token", token_key),
+    ]))
+}
+
+pub fn execute_update_reward_token(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: Denom,
+    daily_reward_amount: Uint128,
+    apy_prefix: Uint128,
+    eta: u64
+) -> Result<Response, ContractError> {
+    // authorize operator
+    check_role(&deps, &info, Role::Operator)?;
+
+    let token = validate_denom(deps.api, token)?;
+    if !REWARD_TOKENS.has(deps.storage, denom_key(&token)) {
+        return Err(ContractError::UnacceptableToken {});
+    }
+    queue_change(deps, env, ChangeKind::RewardToken { token, daily_reward_amount, apy_prefix }, eta, "update_reward_token")
+}
+
+pub fn execute_set_price(
+    deps: DepsMut,
+    info: MessageInfo,
+    pool_id: u64,
+    token: Denom,
+    price: Option<Decimal>
+) -> Result<Response, ContractError> {
+    // authorize operator
+    check_role(&deps, &info, Role::Operator)?;
+
+    if !POOLS.has(deps.storage, pool_id) {
+        return Err(ContractError::PoolNotFound {});
+    }
+    let token_key = denom_key(&token);
+    if !REWARD_TOKENS.has(deps.storage, token_key.clone()) {
+        return Err(ContractError::UnacceptableToken {});
+    }
+    match price {
+        Some(price) => PRICES.save(deps.storage, (pool_id, token_key.clone()), &price)?,
+        None => PRICES.remove(deps.storage, (pool_id, token_key.clone())),
     }
 
-    let mut exists = UNSTAKING.may_load(deps.storage, info.sender.clone())?;
-    let mut unstaking = vec![];
-    if exists.is_some() {
-        unstaking = exists.unwrap();
-    } 
-
-    unstaking.push((unstake_amount, env.block.time.seconds() + cfg.lock_days * 86400u64));
-    UNSTAKING.save(deps.storage, info.sender.clone(), &unstaking)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_price"),
+        attr("pool_id", pool_id.to_string()),
+        attr("reward_token", token_key),
+        attr("price", price.map(|p| p.to_string()).unwrap_or_else(|| "none".to_string())),
+    ]))
+}
 
-    STAKERS.save(deps.storage, info.sender.clone(), &(amount - unstake_amount, reward, last_time))?;
+pub fn execute_set_pair_address(
+    deps: DepsMut,
+    info: MessageInfo,
+    pair_address: Option<String>
+) -> Result<Response, ContractError> {
+    // authorize operator
+    check_role(&deps, &info, Role::Operator)?;
 
-    // ++ Added: update stake_amount excluding unstake_amount
-    // update_stake_amount(deps.storage, env.clone(), unstake_amount);
+    let mut cfg = CONFIG.load(deps.storage)?;
+    cfg.pair_address = pair_address
+        .map(|pair| deps.api.addr_validate(&pair))
+        .transpose()?;
+    CONFIG.save(deps.storage, &cfg)?;
 
-    return Ok(Response::new()
-        .add_attributes(vec![
-            attr("action", "create_unstake"),
-            attr("address", info.sender.clone()),
-            attr("stake_amount", Uint128::from(amount)),
-        ]));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "set_pair_address"),
+        attr("pair_address", cfg.pair_address.map(|p| p.to_string()).unwrap_or_else(|| "none".to_string())),
+    ]))
 }
 
-
-pub fn try_fetch_unstake(
+pub fn execute_add_pool(
     deps: DepsMut,
     env: Env,
     info: MessageInfo,
-    index: u64
+    stake_token: Denom,
+    alloc_point: u64
 ) -> Result<Response, ContractError> {
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
 
-    check_enabled(&deps, &info)?;
-    update_reward(deps.storage, env.clone(), info.sender.clone(), None)?;
-
-    let mut cfg = CONFIG.load(deps.storage)?;
-    
-    let exist = UNSTAKING.may_load(deps.storage, info.sender.clone())?;
-    let mut list = vec![];
-    if exist.is_some() {
-        list = exist.unwrap();
-    } else {
-        return Err(ContractError::NotCreatedUnstaking {});
+    let stake_token = validate_denom(deps.api, stake_token)?;
+    let stake_token_key = denom_key(&stake_token);
+    if REWARD_TOKENS.has(deps.storage, stake_token_key.clone()) {
+        return Err(ContractError::InvalidInput {});
     }
-    
-    if (list.len() as u64) <= index {
-        return Err(ContractError::NotCreatedUnstaking {});
+    if POOL_IDS.has(deps.storage, stake_token_key.clone()) {
+        return Err(ContractError::PoolExists {});
     }
-    let (mut amount, mut timestamp) = list.get(index as usize).unwrap();
 
-    if cfg.stake_amount < amount {
-        return Err(ContractError::NotEnoughStake {});
-    }
-    if timestamp > env.block.time.seconds() {
-        return Err(ContractError::StillLocked {});
+    // settle emission and every pool share under the old total before the new pool takes its share
+    let mut cfg = CONFIG.load(deps.storage)?;
+    let tokens = update_pools(deps.storage, &cfg, env.block.time.seconds())?;
+
+    let pool_id = POOL_COUNT.load(deps.storage)?;
+    for (token_key, token) in tokens.iter() {
+        POOL_REWARDS.save(deps.storage, (pool_id, token_key.clone()), &(Uint256::zero(), token.reward_per_point_stored))?;
     }
-    cfg.stake_amount -= amount;
+    POOLS.save(deps.storage, pool_id, &Pool {
+        stake_token,
+        total_active_stake: Uint128::zero(),
+        total_unbonding: Uint128::zero(),
+        total_weight: Uint128::zero(),
+        penalty_amount: Uint128::zero(),
+        penalty_per_weight: Uint256::zero(),
+        alloc_point,
+        retired: false,
+        last_update_time: env.block.time.seconds()
+    })?;
+    POOL_IDS.save(deps.storage, stake_token_key.clone(), &pool_id)?;
+    POOL_COUNT.save(deps.storage, &(pool_id + 1))?;
+
+    cfg.total_alloc_point += alloc_point;
     CONFIG.save(deps.storage, &cfg)?;
-    
-    list.remove(index as usize);
-    UNSTAKING.save(deps.storage, info.sender.clone(), &list)?;
-
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: cfg.stake_token_address.clone().into(),
-        msg: to_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: info.sender.clone().into(),
-            amount: Uint128::from(amount),
-        })?,
-        funds: vec![],
-    };
-    
-    return Ok(Response::new()
-        .add_message(exec_cw20_transfer)
-        .add_attributes(vec![
-            attr("action", "fetch_unstake"),
-            attr("address", info.sender.clone()),
-            attr("stake_amount", Uint128::from(amount)),
-        ]));
-}
 
-pub fn check_owner(
-    deps: &DepsMut,
-    info: &MessageInfo
-) -> Result<Response, ContractError> {
-    let cfg = CONFIG.load(deps.storage)?;
-    let owner = cfg.owner.ok_or(ContractError::Unauthorized {})?;
-    if info.sender != owner {
-        return Err(ContractError::Unauthorized {})
-    }
-    Ok(Response::new().add_attribute("action", "check_owner"))
-}
-pub fn check_enabled(
-    deps: &DepsMut,
-    info: &MessageInfo
-) -> Result<Response, ContractError> {
-    let cfg = CONFIG.load(deps.storage)?;
-    if !cfg.enabled {
-        return Err(ContractError::Disabled {})
-    }
-    Ok(Response::new().add_attribute("action", "check_enabled"))
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "add_pool"),
+        attr("pool_id", pool_id.to_string()),
+        attr("stake_token", stake_token_key),
+        attr("alloc_point", alloc_point.to_string()),
+    ]))
 }
 
-pub fn execute_update_config(
+pub fn execute_set_pool(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
-    new_owner: Option<String>,
+    pool_id: u64,
+    alloc_point: u64,
+    retire: bool
 ) -> Result<Response, ContractError> {
-    // authorize owner
-    check_owner(&deps, &info)?;
-    
-    //test code for checking if check_owner works well
-    // return Err(ContractError::InvalidInput {});
-    // if owner some validated to addr, otherwise set to none
-    let mut tmp_owner = None;
-    if let Some(addr) = new_owner {
-        tmp_owner = Some(deps.api.addr_validate(&addr)?)
-    }
-    if tmp_owner == None {
-        return Err(ContractError::InvalidInput {})
+    // authorize admin
+    check_role(&deps, &info, Role::Admin)?;
+
+    if POOLS.may_load(deps.storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?.retired {
+        return Err(ContractError::PoolRetired {});
     }
 
-    CONFIG.update(deps.storage, |mut exists| -> StdResult<_> {
-        exists.owner = tmp_owner;
-        Ok(exists)
-    })?;
+    // settle emission and every pool share under the old weights
+    let mut cfg = CONFIG.load(deps.storage)?;
+    update_pools(deps.storage, &cfg, env.block.time.seconds())?;
+    let mut pool = POOLS.load(deps.storage, pool_id)?;
 
-    Ok(Response::new().add_attribute("action", "update_config"))
-}
+    cfg.total_alloc_point = cfg.total_alloc_point - pool.alloc_point + alloc_point;
+    CONFIG.save(deps.storage, &cfg)?;
 
-pub fn execute_update_constants(
-    deps: DepsMut,
-    info: MessageInfo,
-    daily_reward_amount: Uint128,
-    apy_prefix: Uint128,
-    reward_interval: u64,
-    delta_time: u64,
-    lock_days: u64,
-    enabled: bool
-) -> Result<Response, ContractError> {
-    // authorize owner
-    check_owner(&deps, &info)?;
-    
-    //test code for checking if check_owner works well
-    // return Err(ContractError::InvalidInput {});
-    // if owner some validated to addr, otherwise set to none
-    
-    CONFIG.update(deps.storage, |mut exists| -> StdResult<_> {
-        exists.daily_reward_amount = daily_reward_amount;
-        exists.apy_prefix = apy_prefix;
-        exists.reward_interval = reward_interval;
-        exists.delta_time = delta_time;
-        exists.lock_days = lock_days;
-        exists.enabled = enabled;
-        Ok(exists)
-    })?;
+    pool.alloc_point = alloc_point;
+    pool.retired = retire;
+    POOLS.save(deps.storage, pool_id, &pool)?;
 
-    Ok(Response::new().add_attribute("action", "update_constants"))
+    Ok(Response::new().add_attributes(vec![
+        attr("action", if retire { "retire_pool" } else { "set_pool" }),
+        attr("pool_id", pool_id.to_string()),
+        attr("alloc_point", alloc_point.to_string()),
+    ]))
 }
 
 
 pub fn execute_add_stakers(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    pool_id: u64,
     stakers: Vec<StakerInfo>
 ) -> Result<Response, ContractError> {
-    // authorize owner
-    check_owner(&deps, &info)?;
+    // authorize migrator
+    check_role(&deps, &info, Role::Migrator)?;
+
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut pool = POOLS.may_load(deps.storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?;
+    let tokens = update_reward_tokens(deps.storage, &cfg, now)?;
+    let pool_rewards = update_pool(deps.storage, pool_id, &mut pool, &tokens, now)?;
 
+    let mut hooks = vec![];
     for staker in stakers {
-        STAKERS.save(deps.storage, staker.address.clone(), &(staker.amount, staker.reward, staker.last_time))?;
+        if STAKERS.has(deps.storage, (pool_id, staker.address.clone())) {
+            return Err(ContractError::StakerExists {});
+        }
+        if staker.lock_end > now + staker.lock_days * 86400u64 {
+            return Err(ContractError::InvalidInput {});
+        }
+        // the weight always follows the tier, a lock that has passed carries no boost
+        let lock_days = if staker.lock_end > now { staker.lock_days } else { 0u64 };
+        let weight = lock_weight(&cfg, staker.amount, lock_days)?;
+        pool.total_active_stake += staker.amount;
+        pool.total_weight += weight;
+        save_staker(deps.storage, env.block.height, pool_id, &staker.address, &Staker {
+            amount: staker.amount,
+            weight,
+            lock_days,
+            lock_end: staker.lock_end,
+            last_time: staker.last_time,
+            penalty_reward: Uint128::zero(),
+            penalty_per_weight_paid: pool.penalty_per_weight
+        })?;
+        if weight > staker.amount {
+            LOCK_EXPIRIES.save(deps.storage, (pool_id, staker.lock_end, staker.address.clone()), &(weight - staker.amount))?;
+        }
+        for (token_key, reward_per_token) in pool_rewards.iter() {
+            let reward = staker.rewards.iter()
+                .find(|r| denom_key(&r.token) == *token_key)
+                .map(|r| r.amount)
+                .unwrap_or_default();
+            STAKER_REWARDS.save(deps.storage, (pool_id, staker.address.clone(), token_key.clone()), &(reward, *reward_per_token))?;
+            if !reward.is_zero() {
+                let mut token = REWARD_TOKENS.load(deps.storage, token_key.clone())?;
+                token.reward_owed += reward;
+                REWARD_TOKENS.save(deps.storage, token_key.clone(), &token)?;
+            }
+        }
+        hooks.extend(stake_changed_hooks(deps.storage, pool_id, &staker.address, Uint128::zero(), staker.amount)?);
     }
-    
-    Ok(Response::new().add_attribute("action", "add_stakers"))
+    save_pool(deps.storage, env.block.height, pool_id, &pool)?;
+
+    Ok(Response::new()
+        .add_submessages(hooks)
+        .add_attribute("action", "add_stakers"))
 }
 
+/// Drops a staker, its unbondings, unclaimed reward and penalty share from `pool`, which must be
+/// settled by update_pool into `pool_rewards`. Returns the (stake, unbonding) it held, which the caller pays out.
+fn remove_staker(
+    storage: &mut dyn Storage,
+    height: u64,
+    pool_id: u64,
+    pool: &mut Pool,
+    pool_rewards: &[(String, Uint256)],
+    address: Addr
+) -> StdResult<(Uint128, Uint128)> {
+    let staker = STAKERS.may_load(storage, (pool_id, address.clone()))?.unwrap_or_default();
+    // the forfeited reward is no longer owed and becomes withdrawable surplus
+    for (token_key, reward_per_token) in pool_rewards {
+        let key = (pool_id, address.clone(), token_key.clone());
+        let (reward, reward_per_token_paid) = STAKER_REWARDS.may_load(storage, key.clone())?.unwrap_or_default();
+        let forfeited = earned(staker.weight, reward, reward_per_token_paid, *reward_per_token)?;
+        let mut token = REWARD_TOKENS.load(storage, token_key.clone())?;
+        token.reward_owed -= forfeited.min(token.reward_owed);
+        REWARD_TOKENS.save(storage, token_key.clone(), &token)?;
+        STAKER_REWARDS.remove(storage, key);
+    }
+
+    let unstaking = UNSTAKING
+        .prefix((pool_id, address.clone()))
+        .range(storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let mut unbonding = Uint128::zero();
+    for (id, (amount, _, _)) in unstaking {
+        unbonding += amount;
+        UNSTAKING.remove(storage, (pool_id, address.clone(), id));
+    }
+
+    pool.total_active_stake -= staker.amount;
+    pool.total_weight -= staker.weight;
+    pool.total_unbonding -= unbonding;
+    // the unsettled penalty share is forfeited as well, or it would stay counted in penalty_amount
+    let penalty_reward = earned(staker.weight, staker.penalty_reward, staker.penalty_per_weight_paid, pool.penalty_per_weight)?;
+    pool.penalty_amount -= penalty_reward.min(pool.penalty_amount);
+
+    LOCK_EXPIRIES.remove(storage, (pool_id, staker.lock_end, address.clone()));
+    STAKED_AMOUNTS.remove(storage, (pool_id, address.clone()), height)?;
+    STAKERS.remove(storage, (pool_id, address));
+    Ok((staker.amount, unbonding))
+}
 
 pub fn execute_remove_staker(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    pool_id: u64,
     address: Addr
 ) -> Result<Response, ContractError> {
-    // authorize owner
-    check_owner(&deps, &info)?;
-    
-    STAKERS.remove(deps.storage, address.clone());
-    
-    Ok(Response::new().add_attribute("action", "remove_staker"))
+    // authorize migrator
+    check_role(&deps, &info, Role::Migrator)?;
+
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut pool = POOLS.may_load(deps.storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?;
+    let tokens = update_reward_tokens(deps.storage, &cfg, env.block.time.seconds())?;
+    let pool_rewards = update_pool(deps.storage, pool_id, &mut pool, &tokens, env.block.time.seconds())?;
+
+    let (stake_amount, unbonding) = remove_staker(deps.storage, env.block.height, pool_id, &mut pool, &pool_rewards, address.clone())?;
+    save_pool(deps.storage, env.block.height, pool_id, &pool)?;
+
+    // the stake leaves the pool totals, so it has to leave the contract with its owner
+    let mut messages: Vec<CosmosMsg> = vec![];
+    if !(stake_amount + unbonding).is_zero() {
+        messages.push(transfer_msg(&pool.stake_token, &address, stake_amount + unbonding)?);
+    }
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_submessages(withdrawal_hooks(deps.storage, pool_id, &address, stake_amount, Uint128::zero())?)
+        .add_attributes(vec![
+            attr("action", "remove_staker"),
+            attr("pool_id", pool_id.to_string()),
+            attr("address", address),
+            attr("stake_amount", stake_amount),
+            attr("unbonding_amount", unbonding),
+        ]))
 }
 
 
 
 pub fn execute_remove_all_stakers(
     deps: DepsMut,
+    env: Env,
     info: MessageInfo,
+    pool_id: u64,
     start_after: Option<String>,
-    _limit: Option<u32>
+    limit: Option<u32>
 ) -> Result<Response, ContractError> {
-    // authorize owner
-    check_owner(&deps, &info)?;
-    
+    // authorize migrator
+    check_role(&deps, &info, Role::Migrator)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let addr = maybe_addr(deps.api, start_after)?;
     let start = addr.map(|addr| Bound::exclusive(addr.as_ref()));
     let stakers:StdResult<Vec<_>> = STAKERS
-        .range(deps.storage, start, None, Order::Ascending)
-        .map(|item| map_staker(item))
+        .prefix(pool_id)
+        .keys(deps.storage, start, None, Order::Ascending)
+        .take(limit)
         .collect();
 
     if stakers.is_err() {
         return Err(ContractError::Map2ListFailed {})
     }
-    
-    for item in stakers.unwrap() {
-        STAKERS.remove(deps.storage, item.address.clone());
+
+    let cfg = CONFIG.load(deps.storage)?;
+    let mut pool = POOLS.may_load(deps.storage, pool_id)?.ok_or(ContractError::PoolNotFound {})?;
+    let tokens = update_reward_tokens(deps.storage, &cfg, env.block.time.seconds())?;
+    let pool_rewards = update_pool(deps.storage, pool_id, &mut pool, &tokens, env.block.time.seconds())?;
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut hooks = vec![];
+    for address in stakers.unwrap() {
+        let (stake_amount, unbonding) = remove_staker(deps.storage, env.block.height, pool_id, &mut pool, &pool_rewards, address.clone())?;
+        if !(stake_amount + unbonding).is_zero() {
+            messages.push(transfer_msg(&pool.stake_token, &address, stake_amount + unbonding)?);
+        }
+        hooks.extend(withdrawal_hooks(deps.storage, pool_id, &address, stake_amount, Uint128::zero())?);
     }
-    
-    Ok(Response::new().add_attribute("action", "remove_all_stakers"))
+    save_pool(deps.storage, env.block.height, pool_id, &pool)?;
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_submessages(hooks)
+        .add_attribute("action", "remove_all_stakers"))
 }
 
-pub fn try_withdraw_reward(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
-    
+pub fn execute_add_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String
+) -> Result<Response, ContractError> {
+    // authorize owner
     check_owner(&deps, &info)?;
-     
-    let mut cfg = CONFIG.load(deps.storage)?;
-    
-    let reward_amount = cfg.reward_amount;
-    cfg.reward_amount = Uint128::zero();
-    CONFIG.save(deps.storage, &cfg)?;
 
-    // create transfer cw20 msg
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: cfg.reward_token_address.clone().into(),
-        msg: to_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: info.sender.clone().into(),
-            amount: reward_amount,
-        })?,
-        funds: vec![],
-    };
+    let hook = deps.api.addr_validate(&addr)?;
+    let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    if hooks.contains(&hook) {
+        return Err(ContractError::HookAlreadyRegistered {});
+    }
+    hooks.push(hook.clone());
+    HOOKS.save(deps.storage, &hooks)?;
 
-    return Ok(Response::new()
-        .add_message(exec_cw20_transfer)
-        .add_attributes(vec![
-            attr("action", "reward_withdraw_all"),
-            attr("address", info.sender.clone()),
-            attr("reward_amount", reward_amount),
-        ]));
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "add_hook"),
+        attr("hook", hook),
+    ]))
 }
 
-pub fn try_withdraw_stake(deps: DepsMut, info: MessageInfo) -> Result<Response, ContractError> {
-    
+pub fn execute_remove_hook(
+    deps: DepsMut,
+    info: MessageInfo,
+    addr: String
+) -> Result<Response, ContractError> {
+    // authorize owner
     check_owner(&deps, &info)?;
 
-    let mut cfg = CONFIG.load(deps.storage)?;
-    let stake_amount = cfg.stake_amount;
-    cfg.stake_amount = Uint128::zero();
+    let hook = deps.api.addr_validate(&addr)?;
+    let mut hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    if !hooks.contains(&hook) {
+        return Err(ContractError::HookNotRegistered {});
+    }
+    hooks.retain(|h| *h != hook);
+    HOOKS.save(deps.storage, &hooks)?;
 
-    CONFIG.save(deps.storage, &cfg)?;
+    Ok(Response::new().add_attributes(vec![
+        attr("action", "remove_hook"),
+        attr("hook", hook),
+    ]))
+}
 
-    // create transfer cw20 msg
-    let exec_cw20_transfer = WasmMsg::Execute {
-        contract_addr: cfg.stake_token_address.clone().into(),
-        msg: to_binary(&Cw20ExecuteMsg::Transfer {
-            recipient: info.sender.clone().into(),
-            amount: stake_amount,
-        })?,
-        funds: vec![],
-    };
+pub fn try_withdraw_reward(deps: DepsMut, env: Env, info: MessageInfo) -> Result<Response, ContractError> {
 
-    return Ok(Response::new()
-        .add_message(exec_cw20_transfer)
-        .add_attributes(vec![
-            attr("action", "stake_withdraw_all"),
-            attr("address", info.sender.clone()),
-            attr("stake_amount", stake_amount),
-        ]));
+    check_role(&deps, &info, Role::Admin)?;
+
+    // settle every pool so reward_owed holds everything earned up to now
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(deps.storage)?;
+    update_pools(deps.storage, &cfg, now)?;
+    let tokens = REWARD_TOKENS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut messages: Vec<CosmosMsg> = vec![];
+    let mut attrs = vec![
+        attr("action", "reward_withdraw_all"),
+        attr("address", info.sender.clone()),
+    ];
+    for (token_key, mut token) in tokens {
+        let reward_amount = token.reward_amount
+            .saturating_sub(token.reward_owed)
+            .saturating_sub(remaining_reward(&token, cfg.reward_interval, now));
+        if reward_amount == Uint128::zero() {
+            continue;
+        }
+        token.reward_amount -= reward_amount;
+        REWARD_TOKENS.save(deps.storage, token_key.clone(), &token)?;
+
+        messages.push(transfer_msg(&token.denom, &info.sender, reward_amount)?);
+        attrs.push(attr("reward_token", token_key));
+        attrs.push(attr("reward_amount", reward_amount));
+    }
+
+    Ok(Response::new()
+        .add_messages(messages)
+        .add_attributes(attrs))
 }
 
 
+pub fn execute_recover_tokens(
+    deps: DepsMut,
+    env: Env,
+    info: MessageInfo,
+    token: Denom,
+    amount: Uint128,
+    recipient: String
+) -> Result<Response, ContractError> {
+    // authorize owner
+    check_owner(&deps, &info)?;
+
+    let token = validate_denom(deps.api, token)?;
+    let recipient = deps.api.addr_validate(&recipient)?;
+    if amount == Uint128::zero() {
+        return Err(ContractError::InvalidInput {});
+    }
+
+    let balance = query_token_balance(&deps.querier, &token, &env.contract.address)?;
+    let untracked = balance.saturating_sub(tracked_balance(deps.storage, &token)?);
+    if amount > untracked {
+        return Err(ContractError::UntrackedBalanceExceeded {});
+    }
+
+    Ok(Response::new()
+        .add_message(transfer_msg(&token, &recipient, amount)?)
+        .add_attributes(vec![
+            attr("action", "recover_tokens"),
+            attr("token", denom_key(&token)),
+            attr("recipient", recipient),
+            attr("amount", amount),
+        ]))
+}
+
 
 #[cfg_attr(not(feature = "library"), entry_point)]
-pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
+pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
-        QueryMsg::Config {} 
-            => to_binary(&query_config(deps)?),
-        QueryMsg::Staker {address} 
-            => to_binary(&query_staker(deps, address)?),
-        QueryMsg::ListStakers {start_after, limit} 
-            => to_binary(&query_list_stakers(deps, start_after, limit)?),
-        QueryMsg::Apy {} 
-            => to_binary(&query_apy(deps)?),
-        QueryMsg::Unstaking {address} 
-            => to_binary(&query_unstaking(deps, address)?),
+        QueryMsg::Config {}
+            => to_json_binary(&query_config(deps)?),
+        QueryMsg::PendingOwner {}
+            => to_json_binary(&query_pending_owner(deps)?),
+        QueryMsg::PendingConstantsChanges {}
+            => to_json_binary(&query_pending_constants_changes(deps)?),
+        QueryMsg::Roles {address}
+            => to_json_binary(&query_roles(deps, address)?),
+        QueryMsg::Pool {pool_id}
+            => to_json_binary(&query_pool(deps, pool_id)?),
+        QueryMsg::ListPools {}
+            => to_json_binary(&query_list_pools(deps)?),
+        QueryMsg::Staker {pool_id, address}
+            => to_json_binary(&query_staker(deps, pool_id, address)?),
+        QueryMsg::ListStakers {pool_id, start_after, limit}
+            => to_json_binary(&query_list_stakers(deps, pool_id, start_after, limit)?),
+        QueryMsg::Apy {pool_id}
+            => to_json_binary(&query_apy(deps, pool_id)?),
+        QueryMsg::Tvl {}
+            => to_json_binary(&query_tvl(deps)?),
+        QueryMsg::Apr {pool_id}
+            => to_json_binary(&query_apr(deps, env, pool_id)?),
+        QueryMsg::Unstaking {pool_id, address}
+            => to_json_binary(&query_unstaking(deps, pool_id, address)?),
+        QueryMsg::PendingReward {pool_id, address, at_time}
+            => to_json_binary(&query_pending_reward(deps, env, pool_id, address, at_time)?),
+        QueryMsg::VotingPowerAtHeight {address, height, pool_id}
+            => to_json_binary(&query_voting_power_at_height(deps, env, address, height, pool_id)?),
+        QueryMsg::TotalPowerAtHeight {height, pool_id}
+            => to_json_binary(&query_total_power_at_height(deps, env, height, pool_id)?),
+        QueryMsg::Hooks {}
+            => to_json_binary(&query_hooks(deps)?),
+        QueryMsg::Solvency {}
+            => to_json_binary(&query_solvency(deps, env)?),
+        QueryMsg::SolvencyStakers {pool_id, start_after, limit}
+            => to_json_binary(&query_solvency_stakers(deps, env, pool_id, start_after, limit)?),
     }
 }
 
 pub fn query_config(deps: Deps) -> StdResult<ConfigResponse> {
     let cfg = CONFIG.load(deps.storage)?;
+    let reward_tokens = REWARD_TOKENS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(_, token)| RewardTokenResponse {
+            token: token.denom,
+            reward_amount: token.reward_amount,
+            reward_owed: token.reward_owed,
+            daily_reward_amount: token.daily_reward_amount,
+            apy_prefix: token.apy_prefix,
+            reward_per_point_stored: token.reward_per_point_stored,
+            last_update_time: token.last_update_time,
+            period_finish: token.period_finish
+        }))
+        .collect::<StdResult<Vec<_>>>()?;
     Ok(ConfigResponse {
         owner: cfg.owner.map(|o| o.into()),
-        reward_token_address: cfg.reward_token_address.into(),
-        stake_token_address: cfg.stake_token_address.into(),
-        reward_amount: cfg.reward_amount,
-        stake_amount: cfg.stake_amount,
-        daily_reward_amount: cfg.daily_reward_amount,
-        apy_prefix: cfg.apy_prefix,
         reward_interval: cfg.reward_interval,
         delta_time: cfg.delta_time,
-        lock_days: cfg.lock_days
+        lock_days: cfg.lock_days,
+        total_alloc_point: cfg.total_alloc_point,
+        pool_count: POOL_COUNT.load(deps.storage)?,
+        lock_tiers: cfg.lock_tiers,
+        early_unstake_penalty: cfg.early_unstake_penalty,
+        min_delay: cfg.min_delay,
+        pair_address: cfg.pair_address.map(|p| p.into()),
+        reward_tokens
+    })
+}
+
+fn query_pending_owner(deps: Deps) -> StdResult<PendingOwnerResponse> {
+    let pending = PENDING_OWNER.may_load(deps.storage)?;
+    Ok(PendingOwnerResponse {
+        pending_owner: pending.as_ref().map(|(owner, _)| owner.to_string()),
+        expiry: pending.map(|(_, expiry)| expiry)
     })
 }
 
+fn query_pending_constants_changes(deps: Deps) -> StdResult<Vec<ConstantsChangeResponse>> {
+    CONSTANTS_CHANGES
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(id, change)| ConstantsChangeResponse { id, change }))
+        .collect()
+}
+
+fn query_roles(deps: Deps, address: String) -> StdResult<RolesResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let mut roles = vec![];
+    for role in [Role::Admin, Role::Operator, Role::Funder, Role::Migrator] {
+        if has_role(deps.storage, &address, role)? {
+            roles.push(role);
+        }
+    }
+    Ok(RolesResponse { roles })
+}
+
+fn map_pool(pool_id: u64, pool: Pool) -> PoolResponse {
+    PoolResponse {
+        pool_id,
+        stake_token: pool.stake_token,
+        total_active_stake: pool.total_active_stake,
+        total_unbonding: pool.total_unbonding,
+        total_weight: pool.total_weight,
+        penalty_amount: pool.penalty_amount,
+        alloc_point: pool.alloc_point,
+        retired: pool.retired
+    }
+}
+
+fn query_hooks(deps: Deps) -> StdResult<HooksResponse> {
+    let hooks = HOOKS.may_load(deps.storage)?.unwrap_or_default();
+    Ok(HooksResponse { hooks: hooks.into_iter().map(|h| h.into()).collect() })
+}
+
+fn query_pool(deps: Deps, pool_id: u64) -> StdResult<PoolResponse> {
+    let pool = POOLS.load(deps.storage, pool_id)?;
+    Ok(map_pool(pool_id, pool))
+}
+
+fn query_list_pools(deps: Deps) -> StdResult<Vec<PoolResponse>> {
+    POOLS
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(pool_id, pool)| map_pool(pool_id, pool)))
+        .collect()
+}
+
 // settings for pagination
 const MAX_LIMIT: u32 = 30;
 const DEFAULT_LIMIT: u32 = 10;
 
-fn query_staker(deps: Deps, address: Addr) -> StdResult<StakerResponse> {
-    
-    let exists = STAKERS.may_load(deps.storage, address.clone())?;
-    let (mut amount, mut reward, mut last_time) = (Uint128::zero(), Uint128::zero(), 0u64);
-    if exists.is_some() {
-        (amount, reward, last_time) = exists.unwrap();
-    } 
+fn query_staker(deps: Deps, pool_id: u64, address: Addr) -> StdResult<StakerResponse> {
+
+    let staker = STAKERS.may_load(deps.storage, (pool_id, address.clone()))?.unwrap_or_default();
+    let rewards = STAKER_REWARDS
+        .prefix((pool_id, address.clone()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (token_key, (reward, reward_per_token_paid)) = item?;
+            Ok(StakerRewardResponse {
+                token: REWARD_TOKENS.load(deps.storage, token_key)?.denom,
+                reward,
+                reward_per_token_paid
+            })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
     Ok(StakerResponse {
         address,
-        amount,
-        reward,
-        last_time
+        amount: staker.amount,
+        weight: staker.weight,
+        lock_days: staker.lock_days,
+        lock_end: staker.lock_end,
+        penalty_reward: staker.penalty_reward,
+        rewards,
+        last_time: staker.last_time
     })
 }
 
 
-fn query_unstaking(deps: Deps, address: Addr) -> StdResult<Vec<(Uint128, u64)>> {
-    
-    let exists = UNSTAKING.may_load(deps.storage, address.clone())?;
-    let mut unstaking = vec![];
-    if exists.is_some() {
-        unstaking = exists.unwrap();
-    } 
-    Ok(unstaking)
+/// (id, amount, unlock time) of every unbonding of the staker
+fn query_unstaking(deps: Deps, pool_id: u64, address: Addr) -> StdResult<Vec<(u64, Uint128, u64)>> {
+
+    UNSTAKING
+        .prefix((pool_id, address))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| item.map(|(id, (amount, unlock_time, _))| (id, amount, unlock_time)))
+        .collect()
 }
 
 fn map_staker(
-    item: StdResult<(Addr, (Uint128, Uint128, u64))>,
+    deps: Deps,
+    pool_id: u64,
+    item: StdResult<(Addr, Staker)>,
 ) -> StdResult<StakerInfo> {
-    item.map(|(address, (amount, reward, last_time))| {
-        StakerInfo {
-            address,
-            amount,
-            reward,
-            last_time
-        }
+    let (address, staker) = item?;
+    let rewards = STAKER_REWARDS
+        .prefix((pool_id, address.clone()))
+        .range(deps.storage, None, None, Order::Ascending)
+        .map(|item| {
+            let (token_key, (amount, _)) = item?;
+            Ok(RewardAmount { token: REWARD_TOKENS.load(deps.storage, token_key)?.denom, amount })
+        })
+        .collect::<StdResult<Vec<_>>>()?;
+    Ok(StakerInfo {
+        address,
+        amount: staker.amount,
+        weight: staker.weight,
+        lock_days: staker.lock_days,
+        lock_end: staker.lock_end,
+        rewards,
+        last_time: staker.last_time
     })
 }
 
 fn query_list_stakers(
     deps: Deps,
+    pool_id: u64,
     start_after: Option<String>,
     limit: Option<u32>,
 ) -> StdResult<StakerListResponse> {
@@ -600,37 +2156,344 @@ fn query_list_stakers(
     let start = addr.map(|addr| Bound::exclusive(addr.as_ref()));
 
     let stakers:StdResult<Vec<_>> = STAKERS
+        .prefix(pool_id)
         .range(deps.storage, start, None, Order::Ascending)
         .take(limit)
-        .map(|item| map_staker(item))
+        .map(|item| map_staker(deps, pool_id, item))
         .collect();
 
     Ok(StakerListResponse { stakers: stakers? })
 }
 
-pub fn query_apy(deps: Deps) -> StdResult<Uint128> {
+pub fn query_apy(deps: Deps, pool_id: u64) -> StdResult<Vec<ApyResponse>> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let pool = POOLS.load(deps.storage, pool_id)?;
+    let tokens = REWARD_TOKENS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    // unbonding stake earns nothing
+    let total_staked = pool.total_active_stake;
+    if total_staked == Uint128::zero() || cfg.total_alloc_point == 0 {
+        return Ok(tokens.into_iter()
+            .map(|(_, token)| ApyResponse { token: token.denom, apy: Uint128::zero() })
+            .collect());
+    }
+    // For integer handling, return apy * APY_MULTIPLE(10^10)
+
+    let stake_current_supply = match &pool.stake_token {
+        Denom::Native(denom) => deps.querier.query_supply(denom)?.amount,
+        Denom::Cw20(address) => {
+            let stake_token_info: TokenInfoResponse =
+                deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
+                    contract_addr: address.to_string(),
+                    msg: to_json_binary(&Cw20QueryMsg::TokenInfo {})?,
+                }))?;
+            stake_token_info.total_supply
+        },
+    };
+
+    let stake_rate = stake_current_supply.checked_div(Uint128::from(10_000_000_000u128))?
+        .checked_add(Uint128::from(10000u128))?;
+
+    // apy_prefix is for the whole emission, the pool only gets its alloc_point share
+    tokens.into_iter()
+        .map(|(_, info)| -> StdResult<_> {
+            let apy = info.apy_prefix.checked_multiply_ratio(pool.alloc_point, cfg.total_alloc_point)
+                .map_err(|e| StdError::generic_err(e.to_string()))?
+                .checked_mul(Uint128::from(APY_MULTIPLE))?
+                .checked_mul(Uint128::from(APY_MULTIPLE))?
+                .checked_div(stake_rate)?
+                .checked_div(total_staked)?;
+            Ok(ApyResponse { token: info.denom, apy })
+        })
+        .collect()
+}
+
+/// Same accrual as update_reward, nothing is saved
+fn query_pending_reward(
+    deps: Deps,
+    env: Env,
+    pool_id: u64,
+    address: String,
+    at_time: Option<u64>
+) -> StdResult<PendingRewardResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    // accumulators never go back, so an earlier time reads as now
+    let at_time = at_time.unwrap_or_default().max(env.block.time.seconds());
+    let cfg = CONFIG.load(deps.storage)?;
+    let pool = POOLS.load(deps.storage, pool_id)?;
+    let tokens = reward_tokens_at(deps.storage, &cfg, at_time)?;
+    let pool_rewards = pool_rewards_at(deps.storage, pool_id, &pool, &tokens, at_time)?;
+
+    let staker = STAKERS.may_load(deps.storage, (pool_id, address.clone()))?.unwrap_or_default();
+    let rewards = pending_rewards(deps.storage, pool_id, &address, &staker, &pool_rewards)?;
+    Ok(PendingRewardResponse {
+        penalty_reward: earned(staker.weight, staker.penalty_reward, staker.penalty_per_weight_paid, pool.penalty_per_weight)?,
+        rewards: tokens.into_iter().zip(rewards)
+            .map(|((_, token), (_, amount))| RewardAmount { token: token.denom, amount })
+            .collect(),
+        address,
+        at_time
+    })
+}
+
+fn query_voting_power_at_height(
+    deps: Deps,
+    env: Env,
+    address: String,
+    height: Option<u64>,
+    pool_id: Option<u64>
+) -> StdResult<VotingPowerAtHeightResponse> {
+    let address = deps.api.addr_validate(&address)?;
+    let key = (pool_id.unwrap_or_default(), address);
+    let power = match height {
+        Some(height) => STAKED_AMOUNTS.may_load_at_height(deps.storage, key, height)?,
+        None => STAKED_AMOUNTS.may_load(deps.storage, key)?,
+    };
+    Ok(VotingPowerAtHeightResponse {
+        power: power.unwrap_or_default(),
+        height: height.unwrap_or(env.block.height)
+    })
+}
+
+fn query_total_power_at_height(
+    deps: Deps,
+    env: Env,
+    height: Option<u64>,
+    pool_id: Option<u64>
+) -> StdResult<TotalPowerAtHeightResponse> {
+    let pool_id = pool_id.unwrap_or_default();
+    let power = match height {
+        Some(height) => TOTAL_STAKED.may_load_at_height(deps.storage, pool_id, height)?,
+        None => TOTAL_STAKED.may_load(deps.storage, pool_id)?,
+    };
+    Ok(TotalPowerAtHeightResponse {
+        power: power.unwrap_or_default(),
+        height: height.unwrap_or(env.block.height)
+    })
+}
+
+fn query_solvency(deps: Deps, env: Env) -> StdResult<Vec<SolvencyResponse>> {
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(deps.storage)?;
+    let tokens = reward_tokens_at(deps.storage, &cfg, now)?;
+
+    // rewards accrued to pools since their last settlement are owed as well
+    let mut reward_owed: Vec<Uint128> = tokens.iter().map(|(_, token)| token.reward_owed).collect();
+    let pools = POOLS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    for (pool_id, pool) in pools.iter() {
+        let pool_rewards = pool_rewards_at(deps.storage, *pool_id, pool, &tokens, now)?;
+        for (owed, (_, _, accrued)) in reward_owed.iter_mut().zip(pool_rewards.rewards) {
+            *owed += accrued;
+        }
+    }
+
+    let mut report: Vec<SolvencyResponse> = vec![];
+    for (_, pool) in pools {
+        report.push(SolvencyResponse {
+            token: pool.stake_token,
+            balance: Uint128::zero(),
+            stake_owed: pool.total_active_stake + pool.total_unbonding + pool.penalty_amount,
+            reward_owed: Uint128::zero(),
+            reward_committed: Uint128::zero(),
+            surplus: Uint128::zero(),
+            deficit: Uint128::zero()
+        });
+    }
+    for ((_, token), owed) in tokens.into_iter().zip(reward_owed) {
+        report.push(SolvencyResponse {
+            reward_committed: remaining_reward(&token, cfg.reward_interval, now),
+            token: token.denom,
+            balance: Uint128::zero(),
+            stake_owed: Uint128::zero(),
+            reward_owed: owed,
+            surplus: Uint128::zero(),
+            deficit: Uint128::zero()
+        });
+    }
+
+    for r in report.iter_mut() {
+        r.balance = query_token_balance(&deps.querier, &r.token, &env.contract.address)?;
+        let owed = r.stake_owed + r.reward_owed + r.reward_committed;
+        if r.balance >= owed {
+            r.surplus = r.balance - owed;
+        } else {
+            r.deficit = owed - r.balance;
+        }
+    }
+    Ok(report)
+}
+
+fn query_solvency_stakers(
+    deps: Deps,
+    env: Env,
+    pool_id: u64,
+    start_after: Option<String>,
+    limit: Option<u32>,
+) -> StdResult<StakerSolvencyResponse> {
+    let now = env.block.time.seconds();
     let cfg = CONFIG.load(deps.storage)?;
-    let total_staked = cfg.stake_amount;
-    if total_staked == Uint128::zero() {
-        return Ok(Uint128::zero());
+    let pool = POOLS.load(deps.storage, pool_id)?;
+    let tokens = reward_tokens_at(deps.storage, &cfg, now)?;
+    let pool_rewards = pool_rewards_at(deps.storage, pool_id, &pool, &tokens, now)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
+    let addr = maybe_addr(deps.api, start_after)?;
+    let start = addr.map(|addr| Bound::exclusive(addr.as_ref()));
+    let stakers = STAKERS
+        .prefix(pool_id)
+        .range(deps.storage, start, None, Order::Ascending)
+        .take(limit)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut amount = Uint128::zero();
+    let mut unbonding = Uint128::zero();
+    let mut penalty_reward = Uint128::zero();
+    let mut rewards: Vec<Uint128> = vec![Uint128::zero(); tokens.len()];
+    for (address, staker) in stakers.iter() {
+        amount += staker.amount;
+        for item in UNSTAKING
+            .prefix((pool_id, address.clone()))
+            .range(deps.storage, None, None, Order::Ascending) {
+            let (_, (unstake_amount, _, _)) = item?;
+            unbonding += unstake_amount;
+        }
+        penalty_reward += earned(staker.weight, staker.penalty_reward, staker.penalty_per_weight_paid, pool.penalty_per_weight)?;
+        let pending = pending_rewards(deps.storage, pool_id, address, staker, &pool_rewards)?;
+        for (sum, (_, reward)) in rewards.iter_mut().zip(pending) {
+            *sum += reward;
+        }
     }
-    // For integer handling, return apy * MULTIPLE(10^10)
 
-    let stake_token_info: TokenInfoResponse =
-        deps.querier.query(&QueryRequest::Wasm(WasmQuery::Smart {
-            contract_addr: cfg.stake_token_address.clone().into(),
-            msg: to_binary(&Cw20QueryMsg::TokenInfo {})?,
-        }))?;
-    
-    let stake_current_supply = Uint128::from(stake_token_info.total_supply);
+    Ok(StakerSolvencyResponse {
+        pool_id,
+        amount,
+        unbonding,
+        penalty_reward,
+        rewards: tokens.into_iter().zip(rewards)
+            .map(|((_, token), amount)| RewardAmount { token: token.denom, amount })
+            .collect(),
+        last: if stakers.len() < limit { None } else { stakers.last().map(|(address, _)| address.clone()) }
+    })
+}
+/// Reserves of the configured pair if `pool` stakes its LP token
+pub fn pool_pair(deps: Deps, cfg: &Config, pool: &Pool) -> StdResult<Option<PairInfoResponse>> {
+    let pair_address = match &cfg.pair_address {
+        Some(pair_address) => pair_address,
+        None => return Ok(None),
+    };
+    let info: PairInfoResponse = deps.querier.query_wasm_smart(pair_address, &PairQueryMsg::Info {})?;
+    if pool.stake_token != Denom::Cw20(Addr::unchecked(&info.lp_token_address)) {
+        return Ok(None);
+    }
+    Ok(Some(info))
+}
+
+/// Reward token units one stake token of the pool is worth.
+/// An LP share is worth twice its part of the reward token reserve, set prices are used for other tokens.
+pub fn stake_price(
+    deps: Deps,
+    pool_id: u64,
+    pair: Option<&PairInfoResponse>,
+    token_key: &str,
+    token: &Denom
+) -> StdResult<Option<Decimal>> {
+    if let Some(pair) = pair {
+        let reserve = if &pair.token1_denom == token {
+            Some(pair.token1_reserve)
+        } else if &pair.token2_denom == token {
+            Some(pair.token2_reserve)
+        } else {
+            None
+        };
+        if let Some(reserve) = reserve {
+            if pair.lp_token_supply.is_zero() {
+                return Ok(None);
+            }
+            return Decimal::checked_from_ratio(reserve.checked_mul(Uint128::new(2))?, pair.lp_token_supply)
+                .map(Some)
+                .map_err(|e| StdError::generic_err(e.to_string()));
+        }
+    }
+    PRICES.may_load(deps.storage, (pool_id, token_key.to_string()))
+}
 
-    let stake_rate = (stake_current_supply.checked_div(Uint128::from(10_000_000_000u128)).unwrap())
-    .checked_add(Uint128::from(10000u128)).unwrap();
-    
-    Ok(cfg.apy_prefix.checked_mul(Uint128::from(MULTIPLE)).unwrap().checked_mul(Uint128::from(MULTIPLE)).unwrap().checked_div(stake_rate).unwrap().checked_div(total_staked).unwrap())
+fn query_tvl(deps: Deps) -> StdResult<Vec<TvlResponse>> {
+    let cfg = CONFIG.load(deps.storage)?;
+    let tokens = REWARD_TOKENS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+    let pools = POOLS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let mut tvls = vec![];
+    for (pool_id, pool) in pools {
+        let pair = pool_pair(deps, &cfg, &pool)?;
+        let staked = pool.total_active_stake + pool.total_unbonding;
+        let mut values = vec![];
+        for (token_key, token) in tokens.iter() {
+            if let Some(price) = stake_price(deps, pool_id, pair.as_ref(), token_key, &token.denom)? {
+                values.push(RewardAmount {
+                    token: token.denom.clone(),
+                    amount: staked.checked_mul_floor(price).map_err(|e| StdError::generic_err(e.to_string()))?
+                });
+            }
+        }
+        tvls.push(TvlResponse { pool_id, stake_token: pool.stake_token, staked, values });
+    }
+    Ok(tvls)
+}
 
+fn query_apr(deps: Deps, env: Env, pool_id: u64) -> StdResult<Vec<AprResponse>> {
+    let now = env.block.time.seconds();
+    let cfg = CONFIG.load(deps.storage)?;
+    let pool = POOLS.load(deps.storage, pool_id)?;
+    let tokens = REWARD_TOKENS
+        .range(deps.storage, None, None, Order::Ascending)
+        .collect::<StdResult<Vec<_>>>()?;
+
+    let pair = pool_pair(deps, &cfg, &pool)?;
+    let mut aprs = vec![];
+    for (token_key, token) in tokens {
+        let price = match stake_price(deps, pool_id, pair.as_ref(), &token_key, &token.denom)? {
+            Some(price) => price,
+            None => continue,
+        };
+        let staked_value = Decimal::checked_from_ratio(pool.total_weight, 1u128)
+            .map_err(|e| StdError::generic_err(e.to_string()))?
+            .checked_mul(price)?;
+        // emission of the running program only, a finished one pays nothing
+        let apr = if now >= token.period_finish || cfg.reward_interval == 0 || cfg.total_alloc_point == 0 || staked_value.is_zero() {
+            Decimal::zero()
+        } else {
+            let yearly = token.daily_reward_amount.checked_multiply_ratio(
+                SECONDS_PER_YEAR as u128 * pool.alloc_point as u128,
+                cfg.reward_interval as u128 * cfg.total_alloc_point as u128
+            ).map_err(|e| StdError::generic_err(e.to_string()))?;
+            Decimal::checked_from_ratio(yearly, 1u128)
+                .map_err(|e| StdError::generic_err(e.to_string()))?
+                .checked_div(staked_value)
+                .map_err(|e| StdError::generic_err(e.to_string()))?
+        };
+        let daily = apr.checked_div(Decimal::from_ratio(365u128, 1u128))
+            .map_err(|e| StdError::generic_err(e.to_string()))?;
+        let apy = Decimal::one().checked_add(daily)?.checked_pow(365).ok()
+            .map(|compounded| compounded - Decimal::one());
+        aprs.push(AprResponse { token: token.denom, apr, apy });
+    }
+    Ok(aprs)
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn reply(_deps: DepsMut, _env: Env, msg: Reply) -> Result<Response, ContractError> {
+    match msg.id {
+        HOOK_REPLY_ID => Ok(Response::new().add_attribute("action", "hook_failed")),
+        id => Err(StdError::generic_err(format!("Unknown reply id {}", id)).into()),
+    }
+}
 
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
@@ -640,6 +2503,12 @@ pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, C
             previous_contract: version.contract,
         });
     }
+    // 0.0.x kept a single pool and per staker reward, that state can't be carried over
+    if version.version.starts_with("0.0.") {
+        return Err(ContractError::CannotMigrateVersion {
+            previous_version: version.version,
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
     Ok(Response::default())
 }
-
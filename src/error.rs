@@ -1,5 +1,5 @@
 use cosmwasm_std::{StdError};
-use cw_utils::{Expiration, Scheduled};
+use cw_utils::{Expiration, PaymentError, Scheduled};
 use hex::FromHexError;
 use thiserror::Error;
 
@@ -11,24 +11,54 @@ pub enum ContractError {
     #[error("{0}")]
     Hex(#[from] FromHexError),
 
+    #[error("{0}")]
+    Payment(#[from] PaymentError),
+
     #[error("Unauthorized")]
     Unauthorized {},
 
     #[error("Disabled")]
     Disabled {},
 
+    #[error("Only available while the contract is disabled")]
+    NotDisabled {},
+
     #[error("InvalidInput")]
     InvalidInput {},
 
     #[error("Not Reward or Stake token")]
     UnacceptableToken {},
 
+    #[error("Receive message does not match the sent token")]
+    TokenMismatch {},
+
+    #[error("Reward token already registered")]
+    RewardTokenExists {},
+
+    #[error("Pool not found")]
+    PoolNotFound {},
+
+    #[error("Pool already exists for this token")]
+    PoolExists {},
+
+    #[error("Pool is retired")]
+    PoolRetired {},
+
+    #[error("Staker already exists")]
+    StakerExists {},
+
+    #[error("Position is locked on another lock tier")]
+    MixedLockTiers {},
+
     #[error("Not enough Stake")]
     NotEnoughStake {},
 
     #[error("Still Locked")]
     StillLocked {},
 
+    #[error("Early exit is closed")]
+    EarlyExitClosed {},
+
     #[error("No Reward")]
     NoReward {},
 
@@ -44,6 +74,33 @@ pub enum ContractError {
     #[error("Still in Lock period")]
     StillInLock { },
 
+    #[error("No lock tier for the given days")]
+    LockTierNotFound {},
+
+    #[error("No ownership proposal")]
+    NoPendingOwner {},
+
+    #[error("Ownership proposal expired")]
+    OwnershipProposalExpired {},
+
+    #[error("Constants change not found")]
+    ConstantsChangeNotFound {},
+
+    #[error("Eta must be at least {min_delay} seconds from now")]
+    EtaTooSoon { min_delay: u64 },
+
+    #[error("Timelock not expired")]
+    TimelockNotExpired {},
+
+    #[error("Amount exceeds the untracked balance")]
+    UntrackedBalanceExceeded {},
+
+    #[error("Hook already registered")]
+    HookAlreadyRegistered {},
+
+    #[error("Hook not registered")]
+    HookNotRegistered {},
+
 
     #[error("Already claimed")]
     Claimed {},
@@ -57,6 +114,9 @@ pub enum ContractError {
     #[error("Cannot migrate from different contract type: {previous_contract}")]
     CannotMigrate { previous_contract: String },
 
+    #[error("Cannot migrate from version {previous_version}, deploy a new contract instead")]
+    CannotMigrateVersion { previous_version: String },
+
     #[error("Airdrop stage {stage} expired at {expiration}")]
     StageExpired { stage: u8, expiration: Expiration },
 
@@ -1,90 +1,286 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
-use cw20::{Cw20ReceiveMsg};
-use cosmwasm_std::{Uint128, Addr};
+use cw20::{Cw20ReceiveMsg, Denom};
+use cw_utils::Expiration;
+use cosmwasm_std::{to_json_binary, Addr, Binary, CosmosMsg, Decimal, StdResult, Uint128, Uint256, WasmMsg};
+use crate::state::{ConstantsChange, EarlyUnstakePenalty, LockTier, Role};
 
 #[derive(Serialize, Deserialize, JsonSchema)]
 pub struct InstantiateMsg {
     /// Owner if none set to info.sender.
     pub owner: Option<String>,
-    pub reward_token_address: Addr,
-    pub stake_token_address: Addr,
+    /// First reward token, more can be registered with AddRewardToken
+    pub reward_token: Denom,
+    /// Stake token of the first pool, more can be added with AddPool
+    pub stake_token: Denom,
     pub daily_reward_amount: Uint128,
     pub apy_prefix: Uint128,
     pub reward_interval: u64,
     pub delta_time: u64,
     pub lock_days: u64,
-    pub enabled: bool
+    pub enabled: bool,
+    /// Timelock of UpdateConstants in seconds, defaults to one day
+    pub min_delay: Option<u64>,
+    /// Defaults to 0, 30, 90 and 365 days at 1x, 1.25x, 1.5x and 2x. The 0-day tier must be 1x
+    pub lock_tiers: Option<Vec<LockTier>>,
+    pub pair_address: Option<String>
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardAmount {
+    pub token: Denom,
+    pub amount: Uint128
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct StakerInfo {
     pub address: Addr,
     pub amount: Uint128,
-    pub reward: Uint128,
+    pub weight: Uint128,
+    pub lock_days: u64,
+    pub lock_end: u64,
+    pub rewards: Vec<RewardAmount>,
     pub last_time: u64
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ExecuteMsg {
-    UpdateConfig {
-        /// NewOwner if non sent, contract gets locked. Recipients can receive airdrops
-        /// but owner cannot register new stages.
-        new_owner: Option<String>,
+    /// Ownership only moves once `new_owner` sends AcceptOwnership, before `expiry` if set
+    ProposeOwner {
+        new_owner: String,
+        expiry: Option<Expiration>
+    },
+    AcceptOwnership {},
+    CancelOwnershipProposal {},
+    /// Leaves the contract without owner, every owner and role action is frozen for good
+    RenounceOwnership {},
+    GrantRole {
+        address: String,
+        role: Role
+    },
+    RevokeRole {
+        address: String,
+        role: Role
+    },
+    /// Pauses or unpauses staking, unstaking and claiming
+    SetEnabled {
+        enabled: bool
     },
+    /// Queues a constants change, `eta` must be at least min_delay from now
     UpdateConstants {
-        daily_reward_amount: Uint128,
-        apy_prefix: Uint128,
         reward_interval: u64,
         delta_time: u64,
         lock_days: u64,
-        enabled: bool
+        early_unstake_penalty: EarlyUnstakePenalty,
+        min_delay: u64,
+        eta: u64
+    },
+    /// Applies a queued change once its eta has passed
+    ExecuteConstantsChange {
+        id: u64
+    },
+    CancelConstantsChange {
+        id: u64
+    },
+    /// Queued like UpdateConstants. Only affects new stake, existing positions keep their multiplier
+    UpdateLockTiers {
+        lock_tiers: Vec<LockTier>,
+        eta: u64
+    },
+    AddRewardToken {
+        token: Denom,
+        apy_prefix: Uint128
+    },
+    /// Queued like UpdateConstants
+    UpdateRewardToken {
+        token: Denom,
+        daily_reward_amount: Uint128,
+        apy_prefix: Uint128,
+        eta: u64
+    },
+    /// Reward token units one stake token of the pool is worth, None removes it
+    SetPrice {
+        pool_id: u64,
+        token: Denom,
+        price: Option<Decimal>
+    },
+    /// Pair priced from its reserves, None falls back to SetPrice prices only
+    SetPairAddress {
+        pair_address: Option<String>
+    },
+    AddPool {
+        stake_token: Denom,
+        alloc_point: u64
+    },
+    SetPool {
+        pool_id: u64,
+        alloc_point: u64
+    },
+    /// Stops new stake and reward for the pool, stakers can still claim and unstake
+    RetirePool {
+        pool_id: u64
     },
     Receive(Cw20ReceiveMsg),
+    /// Native counterparts of ReceiveMsg, the coin is taken from the attached funds
+    Stake {
+        lock_days: Option<u64>
+    },
+    StakeFor {
+        recipient: String
+    },
+    InitialFund {
+        duration: u64
+    },
+    /// Withdraws the reward neither earned by stakers nor committed to the running programs
     WithdrawReward { },
-    WithdrawStake { },
-    ClaimReward { },
+    /// Sends tokens that reached the contract outside staking and funding. Stake and reward
+    /// tokens can only be recovered above their tracked balance.
+    RecoverTokens {
+        token: Denom,
+        amount: Uint128,
+        recipient: String
+    },
+    /// Registers a contract to be sent StakeChangedHookMsg. A failing hook reverts a stake change,
+    /// but never holds up a withdrawal
+    AddHook {
+        addr: String
+    },
+    RemoveHook {
+        addr: String
+    },
+    ClaimReward {
+        pool_id: u64
+    },
     CreateUnstake {
+        pool_id: u64,
         unstake_amount: Uint128
     },
+    /// `id` as returned by CreateUnstake
     FetchUnstake {
-        index: u64
+        pool_id: u64,
+        id: u64
     },
+    /// Moves `amount` (the whole entry if none) of an unbonding back into the stake, without lock.
+    /// Refused while the stake is locked
+    CancelUnstake {
+        pool_id: u64,
+        id: u64,
+        amount: Option<Uint128>
+    },
+    /// Fetches an unbonding before its unlock time, minus the early unstake penalty. Refused while the penalty is 100 percent
+    FetchEarly {
+        pool_id: u64,
+        id: u64
+    },
+    /// Only while disabled, returns the whole stake and unbonding of the pool right away, even if locked.
+    /// Unclaimed reward and penalty share are forfeited.
+    EmergencyWithdraw {
+        pool_id: u64
+    },
+    /// Fetches every unbonding of the pool whose lock has passed in a single transfer
+    FetchAllMatured {
+        pool_id: u64
+    },
+    /// Imports stakers. The weight is derived from the lock tier of `lock_days`, the given one is ignored
     AddStakers {
+        pool_id: u64,
         stakers: Vec<StakerInfo>
     },
+    /// Sends the staker its stake and unbondings back, unclaimed reward is forfeited
     RemoveStaker {
+        pool_id: u64,
         address: Addr
     },
+    /// RemoveStaker for the next `limit` stakers of the pool after `start_after`
     RemoveAllStakers {
+        pool_id: u64,
         start_after: Option<String>,
         limit: Option<u32>
     }
 }
 
+/// Payload of the cw20 Send hook, must match the token being sent
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum ReceiveMsg {
-    Stake {},
-    InitialFund {},
+    /// Stake token of an active pool only. `lock_days` picks a lock tier, the whole position
+    /// can not be unstaked until the lock ends.
+    Stake {
+        lock_days: Option<u64>
+    },
+    /// Stake token of an active pool only. Credits the stake and its reward to `recipient` instead of the sender.
+    /// Joins the running lock of the recipient at its multiplier without extending it, otherwise staked without lock.
+    StakeFor {
+        recipient: String
+    },
+    /// Reward token only. Funds a reward program running for `duration` seconds from now.
+    /// Reward left from a running program is rolled into the new one.
+    InitialFund {
+        duration: u64
+    },
 }
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum QueryMsg {
     Config {},
+    PendingOwner {},
+    /// Queued constants changes, (id, change)
+    PendingConstantsChanges {},
+    /// Roles `address` can act with, the owner and admins hold all of them. Nobody holds any once ownership is renounced
+    Roles {
+        address: String
+    },
+    Pool {
+        pool_id: u64
+    },
+    ListPools {},
     Staker {
+        pool_id: u64,
         address: Addr
     },
     ListStakers {
+        pool_id: u64,
         start_after: Option<String>,
         limit: Option<u32>
     },
     Apy {
-
+        pool_id: u64
+    },
+    /// Value of every pool's staked amount in reward token units
+    Tvl {},
+    /// Yearly return of the 1x lock tier per reward token, tokens without a price are left out
+    Apr {
+        pool_id: u64
     },
     Unstaking {
+        pool_id: u64,
         address: Addr
+    },
+    /// Claimable reward at `at_time` (now if not set), assuming stakes and emission stay as they are
+    PendingReward {
+        pool_id: u64,
+        address: String,
+        at_time: Option<u64>
+    },
+    /// Real balances of every pool and reward token against what the contract owes
+    Solvency {},
+    /// cw3 style voting power, active stake in `pool_id` (0 if not set) at the start of `height` (now if not set)
+    VotingPowerAtHeight {
+        address: String,
+        height: Option<u64>,
+        pool_id: Option<u64>
+    },
+    TotalPowerAtHeight {
+        height: Option<u64>,
+        pool_id: Option<u64>
+    },
+    Hooks {},
+    /// Sums over a page of stakers of a pool, to check the pool totals against
+    SolvencyStakers {
+        pool_id: u64,
+        start_after: Option<String>,
+        limit: Option<u32>
     }
 }
 
@@ -92,19 +288,61 @@ pub enum QueryMsg {
 #[serde(rename_all = "snake_case")]
 pub struct ConfigResponse {
     pub owner: Option<String>,
-    pub reward_token_address: String,
-    pub stake_token_address: String,
-    pub reward_amount: Uint128,
-    pub stake_amount: Uint128,
-    pub daily_reward_amount: Uint128,
-    pub apy_prefix: Uint128,
     pub reward_interval: u64,
     pub delta_time: u64,
-    pub lock_days: u64
+    pub lock_days: u64,
+    pub total_alloc_point: u64,
+    pub pool_count: u64,
+    pub lock_tiers: Vec<LockTier>,
+    pub early_unstake_penalty: EarlyUnstakePenalty,
+    pub min_delay: u64,
+    pub pair_address: Option<String>,
+    pub reward_tokens: Vec<RewardTokenResponse>
+}
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PendingOwnerResponse {
+    pub pending_owner: Option<String>,
+    pub expiry: Option<Expiration>
 }
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ConstantsChangeResponse {
+    pub id: u64,
+    pub change: ConstantsChange
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RolesResponse {
+    pub roles: Vec<Role>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PoolResponse {
+    pub pool_id: u64,
+    pub stake_token: Denom,
+    pub total_active_stake: Uint128,
+    pub total_unbonding: Uint128,
+    pub total_weight: Uint128,
+    pub penalty_amount: Uint128,
+    pub alloc_point: u64,
+    pub retired: bool
+}
 
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct RewardTokenResponse {
+    pub token: Denom,
+    pub reward_amount: Uint128,
+    pub reward_owed: Uint128,
+    pub daily_reward_amount: Uint128,
+    pub apy_prefix: Uint128,
+    pub reward_per_point_stored: Uint256,
+    pub last_update_time: u64,
+    pub period_finish: u64
+}
+
+
+/// Contracts before 0.1.0 can't be migrated, their stakers move to a new deployment with AddStakers
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct MigrateMsg {}
 
@@ -121,9 +359,145 @@ pub struct StakerListResponse {
 pub struct StakerResponse {
     pub address: Addr,
     pub amount: Uint128,
-    pub reward: Uint128,
+    pub weight: Uint128,
+    pub lock_days: u64,
+    pub lock_end: u64,
+    pub penalty_reward: Uint128,
+    pub rewards: Vec<StakerRewardResponse>,
     pub last_time: u64
 }
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct StakerRewardResponse {
+    pub token: Denom,
+    pub reward: Uint128,
+    pub reward_per_token_paid: Uint256
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct ApyResponse {
+    pub token: Denom,
+    pub apy: Uint128
+}
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PendingRewardResponse {
+    pub address: Addr,
+    pub at_time: u64,
+    /// Share of early unstake penalties, paid in the stake token
+    pub penalty_reward: Uint128,
+    pub rewards: Vec<RewardAmount>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct SolvencyResponse {
+    pub token: Denom,
+    pub balance: Uint128,
+    /// Active and unbonding stake plus undistributed penalties
+    pub stake_owed: Uint128,
+    /// Reward earned by stakers and not yet claimed
+    pub reward_owed: Uint128,
+    /// Reward still to be emitted by the running program
+    pub reward_committed: Uint128,
+    pub surplus: Uint128,
+    pub deficit: Uint128
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct StakerSolvencyResponse {
+    pub pool_id: u64,
+    pub amount: Uint128,
+    pub unbonding: Uint128,
+    pub penalty_reward: Uint128,
+    pub rewards: Vec<RewardAmount>,
+    /// Pass as `start_after` for the next page, None when done
+    pub last: Option<Addr>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct AprResponse {
+    pub token: Denom,
+    pub apr: Decimal,
+    /// apr compounded daily, None when too large to represent
+    pub apy: Option<Decimal>
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct HooksResponse {
+    pub hooks: Vec<String>
+}
+
+/// Sent to every hook whenever stake of `address` in `pool_id` moves: `old` and `new` are its
+/// active stake, `unbonding` what it still has unbonding after the change
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct StakeChangedHookMsg {
+    pub pool_id: u64,
+    pub address: String,
+    pub old: Uint128,
+    pub new: Uint128,
+    pub unbonding: Uint128
+}
+
+impl StakeChangedHookMsg {
+    /// Serializes the message wrapped as StakeChangedExecuteMsg
+    pub fn into_binary(self) -> StdResult<Binary> {
+        to_json_binary(&StakeChangedExecuteMsg::StakeChangeHook(self))
+    }
+
+    pub fn into_cosmos_msg<T: Into<String>>(self, contract_addr: T) -> StdResult<CosmosMsg> {
+        Ok(WasmMsg::Execute {
+            contract_addr: contract_addr.into(),
+            msg: self.into_binary()?,
+            funds: vec![],
+        }.into())
+    }
+}
+
+/// What a hook contract has to accept in its ExecuteMsg
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum StakeChangedExecuteMsg {
+    StakeChangeHook(StakeChangedHookMsg)
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct VotingPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TotalPowerAtHeightResponse {
+    pub power: Uint128,
+    pub height: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct TvlResponse {
+    pub pool_id: u64,
+    pub stake_token: Denom,
+    /// Active and unbonding stake
+    pub staked: Uint128,
+    /// Staked amount in units of every reward token with a price
+    pub values: Vec<RewardAmount>
+}
+
+/// Query of a Junoswap style pair
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum PairQueryMsg {
+    Info {}
+}
+
+#[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
+pub struct PairInfoResponse {
+    pub token1_reserve: Uint128,
+    pub token1_denom: Denom,
+    pub token2_reserve: Uint128,
+    pub token2_denom: Denom,
+    pub lp_token_supply: Uint128,
+    pub lp_token_address: String
+}
+
 #[derive(Serialize, Deserialize, Clone, PartialEq, JsonSchema, Debug)]
 pub struct CountInfo {
     pub count: u128
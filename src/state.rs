@@ -1,31 +1,243 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Addr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cosmwasm_std::{Addr, Decimal, Uint128, Uint256};
+use cw20::Denom;
+use cw_utils::Expiration;
+use cw_storage_plus::{Item, Map, SnapshotMap, Strategy};
 
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct Config {
     /// Owner If None set, contract is frozen.
     pub owner: Option<Addr>,
-    pub reward_token_address: Addr,
-    pub stake_token_address: Addr,
-    pub reward_amount: Uint128,
-    pub stake_amount: Uint128,
-    pub daily_reward_amount: Uint128,
-    pub apy_prefix: Uint128,
     pub reward_interval: u64,
     pub delta_time: u64,
     pub lock_days: u64,
-    pub enabled: bool
+    pub enabled: bool,
+    /// Sum of alloc_point over all pools
+    pub total_alloc_point: u64,
+    /// Lock durations a staker can pick when staking, must include a 0 day tier
+    pub lock_tiers: Vec<LockTier>,
+    /// Charged on unbondings fetched before their unlock time
+    pub early_unstake_penalty: EarlyUnstakePenalty,
+    /// Minimum seconds between queueing a constants change and its eta
+    pub min_delay: u64,
+    /// Junoswap style pair whose LP token is staked, used to price the stake token
+    pub pair_address: Option<Addr>
+}
+
+/// Change queued behind the timelock, executable from `eta`
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ConstantsChange {
+    pub kind: ChangeKind,
+    pub eta: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Queued by UpdateConstants
+    Constants {
+        reward_interval: u64,
+        delta_time: u64,
+        lock_days: u64,
+        early_unstake_penalty: EarlyUnstakePenalty,
+        min_delay: u64
+    },
+    /// Queued by UpdateLockTiers
+    LockTiers {
+        lock_tiers: Vec<LockTier>
+    },
+    /// Queued by UpdateRewardToken
+    RewardToken {
+        token: Denom,
+        daily_reward_amount: Uint128,
+        apy_prefix: Uint128
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct EarlyUnstakePenalty {
+    /// Percent of the unbonding amount, 100 keeps early exit closed
+    pub percent: u64,
+    /// Scale the penalty down linearly with the time left of the unbonding period
+    pub decay: bool,
+    pub destination: PenaltyDestination
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum PenaltyDestination {
+    Treasury(Addr),
+    /// Shared by the remaining stakers of the pool by weight, paid out with ClaimReward
+    Redistribute,
+    Burn
+}
+
+/// The owner holds every role
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Everything the other roles can do, plus constants, pools, reward tokens, withdrawals and roles
+    Admin,
+    /// Pause and unpause, tune reward emission within what is funded
+    Operator,
+    /// Fund reward programs with InitialFund
+    Funder,
+    /// Import and remove stakers
+    Migrator
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct LockTier {
+    pub days: u64,
+    /// Reward multiplier in percent, 100 is 1x
+    pub multiplier: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Pool {
+    pub stake_token: Denom,
+    /// Staked and earning
+    pub total_active_stake: Uint128,
+    /// Created unstakes not fetched yet, earning nothing
+    pub total_unbonding: Uint128,
+    /// Sum of the boosted weight of the stakers, the pool reward is split over it
+    pub total_weight: Uint128,
+    /// Redistributed early unstake penalty not claimed yet, in stake token
+    pub penalty_amount: Uint128,
+    /// Accumulated redistributed penalty per weight, scaled by MULTIPLE
+    pub penalty_per_weight: Uint256,
+    /// Share of every reward token emission, relative to total_alloc_point
+    pub alloc_point: u64,
+    /// Retired pools take no new stake and earn no reward
+    pub retired: bool,
+    /// Last time the pool share was settled by update_pool
+    pub last_update_time: u64
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, Default, PartialEq, JsonSchema)]
+pub struct Staker {
+    pub amount: Uint128,
+    /// amount boosted by the multiplier of the lock tier, back to amount once lock_end has passed
+    pub weight: Uint128,
+    /// Lock tier of the position, the whole position is locked on a single tier
+    pub lock_days: u64,
+    /// The position cannot be unstaked before this time
+    pub lock_end: u64,
+    pub last_time: u64,
+    /// Share of redistributed early unstake penalties, in stake token
+    pub penalty_reward: Uint128,
+    pub penalty_per_weight_paid: Uint256
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct RewardToken {
+    pub denom: Denom,
+    pub reward_amount: Uint128,
+    /// Part of reward_amount already earned by stakers and not claimed yet
+    pub reward_owed: Uint128,
+    /// Emitted every reward_interval seconds until period_finish, shared by all pools
+    pub daily_reward_amount: Uint128,
+    pub apy_prefix: Uint128,
+    /// Accumulated reward per allocation point, scaled by MULTIPLE
+    pub reward_per_point_stored: Uint256,
+    /// Last time reward_per_point_stored was brought up to date
+    pub last_update_time: u64,
+    /// End of the current reward program, no reward accrues after it
+    pub period_finish: u64
+}
+
+/// Storage key of a token, prefixed so a native denom can never collide with a cw20 address
+pub fn denom_key(denom: &Denom) -> String {
+    match denom {
+        Denom::Native(denom) => format!("native:{}", denom),
+        Denom::Cw20(address) => format!("cw20:{}", address),
+    }
 }
 
 pub const CONFIG_KEY: &str = "config";
 pub const CONFIG: Item<Config> = Item::new(CONFIG_KEY);
 
+pub const PENDING_OWNER_KEY: &str = "pending_owner";
+/// Proposed owner and when the proposal lapses
+pub const PENDING_OWNER: Item<(Addr, Expiration)> = Item::new(PENDING_OWNER_KEY);
+
+pub const ROLES_KEY: &str = "roles";
+pub const ROLES: Map<Addr, Vec<Role>> = Map::new(ROLES_KEY);
+
+pub const CONSTANTS_CHANGE_COUNT_KEY: &str = "constants_change_count";
+pub const CONSTANTS_CHANGE_COUNT: Item<u64> = Item::new(CONSTANTS_CHANGE_COUNT_KEY);
+
+pub const CONSTANTS_CHANGES_KEY: &str = "constants_changes";
+/// id -> queued constants change
+pub const CONSTANTS_CHANGES: Map<u64, ConstantsChange> = Map::new(CONSTANTS_CHANGES_KEY);
+
+pub const POOL_COUNT_KEY: &str = "pool_count";
+pub const POOL_COUNT: Item<u64> = Item::new(POOL_COUNT_KEY);
+
+pub const POOLS_KEY: &str = "pools";
+pub const POOLS: Map<u64, Pool> = Map::new(POOLS_KEY);
+
+pub const POOL_IDS_KEY: &str = "pool_ids";
+/// stake token key -> pool id
+pub const POOL_IDS: Map<String, u64> = Map::new(POOL_IDS_KEY);
+
+pub const REWARD_TOKENS_KEY: &str = "reward_tokens";
+/// reward token key -> reward token
+pub const REWARD_TOKENS: Map<String, RewardToken> = Map::new(REWARD_TOKENS_KEY);
+
+pub const POOL_REWARDS_KEY: &str = "pool_rewards";
+/// (pool, reward token key) -> (reward_per_token_stored, reward_per_point_paid)
+pub const POOL_REWARDS: Map<(u64, String), (Uint256, Uint256)> = Map::new(POOL_REWARDS_KEY);
+
+pub const PRICES_KEY: &str = "prices";
+/// (pool, reward token key) -> reward token units one stake token is worth
+pub const PRICES: Map<(u64, String), Decimal> = Map::new(PRICES_KEY);
+
 pub const STAKERS_KEY: &str = "stakers";
-pub const STAKERS: Map<Addr, (Uint128, Uint128, u64)> = Map::new(STAKERS_KEY);
+/// (pool, staker) -> staker
+pub const STAKERS: Map<(u64, Addr), Staker> = Map::new(STAKERS_KEY);
+
+pub const STAKER_REWARDS_KEY: &str = "staker_rewards";
+/// (pool, staker, reward token key) -> (reward, reward_per_token_paid)
+pub const STAKER_REWARDS: Map<(u64, Addr, String), (Uint128, Uint256)> = Map::new(STAKER_REWARDS_KEY);
+
+pub const LOCK_EXPIRIES_KEY: &str = "lock_expiries";
+/// (pool, lock_end, staker) -> weight the staker loses at lock_end
+pub const LOCK_EXPIRIES: Map<(u64, u64, Addr), Uint128> = Map::new(LOCK_EXPIRIES_KEY);
+
+pub const STAKED_AMOUNTS_KEY: &str = "staked_amounts";
+pub const STAKED_AMOUNTS_CHECKPOINTS_KEY: &str = "staked_amounts__checkpoints";
+pub const STAKED_AMOUNTS_CHANGELOG_KEY: &str = "staked_amounts__changelog";
+/// (pool, staker) -> active stake, by height for voting power
+pub const STAKED_AMOUNTS: SnapshotMap<(u64, Addr), Uint128> = SnapshotMap::new(
+    STAKED_AMOUNTS_KEY,
+    STAKED_AMOUNTS_CHECKPOINTS_KEY,
+    STAKED_AMOUNTS_CHANGELOG_KEY,
+    Strategy::EveryBlock
+);
+
+pub const TOTAL_STAKED_KEY: &str = "total_staked";
+pub const TOTAL_STAKED_CHECKPOINTS_KEY: &str = "total_staked__checkpoints";
+pub const TOTAL_STAKED_CHANGELOG_KEY: &str = "total_staked__changelog";
+/// pool -> total active stake, by height for voting power
+pub const TOTAL_STAKED: SnapshotMap<u64, Uint128> = SnapshotMap::new(
+    TOTAL_STAKED_KEY,
+    TOTAL_STAKED_CHECKPOINTS_KEY,
+    TOTAL_STAKED_CHANGELOG_KEY,
+    Strategy::EveryBlock
+);
+
+pub const HOOKS_KEY: &str = "hooks";
+/// Contracts sent a StakeChangedHookMsg on every change of active or unbonding stake
+pub const HOOKS: Item<Vec<Addr>> = Item::new(HOOKS_KEY);
+
+pub const UNSTAKE_COUNT_KEY: &str = "unstake_count";
+/// Next unstaking id, ids are never reused
+pub const UNSTAKE_COUNT: Item<u64> = Item::new(UNSTAKE_COUNT_KEY);
 
 pub const UNSTAKING_KEY: &str = "unstaking";
-pub const UNSTAKING: Map<Addr, Vec<(Uint128, u64)>> = Map::new(UNSTAKING_KEY);
+/// (pool, staker, id) -> (amount, unlock time, unbonding period in seconds)
+pub const UNSTAKING: Map<(u64, Addr, u64), (Uint128, u64, u64)> = Map::new(UNSTAKING_KEY);
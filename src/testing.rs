@@ -0,0 +1,953 @@
+use cosmwasm_std::testing::{mock_dependencies, mock_env, mock_info, MockApi, MockQuerier, MockStorage, MOCK_CONTRACT_ADDR};
+use cosmwasm_std::{
+    coins, from_json, to_json_binary, Addr, BankMsg, Coin, ContractResult, Decimal, Env, OwnedDeps, Reply, ReplyOn, SubMsg, SubMsgResult,
+    SystemError, SystemResult, Timestamp, Uint128, WasmQuery,
+};
+use cw20::{Cw20ReceiveMsg, Denom};
+use cw_utils::Expiration;
+
+use crate::contract::{execute, instantiate, migrate, pool_pair, query, reply, stake_price};
+use crate::error::ContractError;
+use crate::msg::{
+    ExecuteMsg, InstantiateMsg, MigrateMsg, PairInfoResponse, PendingRewardResponse, QueryMsg, ReceiveMsg, RewardAmount, RolesResponse, StakeChangedHookMsg, StakerInfo,
+    TotalPowerAtHeightResponse, TvlResponse, VotingPowerAtHeightResponse,
+};
+use crate::state::{denom_key, EarlyUnstakePenalty, LockTier, PenaltyDestination, Role, CONFIG, POOLS, REWARD_TOKENS, STAKERS};
+
+type Deps = OwnedDeps<MockStorage, MockApi, MockQuerier>;
+
+fn env_at(seconds: u64) -> Env {
+    let mut env = mock_env();
+    env.block.time = Timestamp::from_seconds(seconds);
+    env.block.height = seconds;
+    env
+}
+
+fn cw20(address: &str) -> Denom {
+    Denom::Cw20(Addr::unchecked(address))
+}
+
+/// Contract with pool 0 staking "lptoken" for "reward", paying `daily_reward_amount` per 100 seconds
+fn setup(daily_reward_amount: u128, pair_address: Option<&str>) -> Deps {
+    let mut deps = mock_dependencies();
+    instantiate(deps.as_mut(), env_at(1000), mock_info("owner", &[]), InstantiateMsg {
+        owner: None,
+        reward_token: cw20("reward"),
+        stake_token: cw20("lptoken"),
+        daily_reward_amount: Uint128::new(daily_reward_amount),
+        apy_prefix: Uint128::new(1),
+        reward_interval: 100,
+        delta_time: 0,
+        lock_days: 0,
+        enabled: true,
+        min_delay: Some(0),
+        lock_tiers: None,
+        pair_address: pair_address.map(|pair| pair.to_string()),
+    }).unwrap();
+    deps
+}
+
+/// Answers PairQueryMsg::Info of "pair" with `info`
+fn mock_pair(deps: &mut Deps, info: PairInfoResponse) {
+    deps.querier.update_wasm(move |query| match query {
+        WasmQuery::Smart { contract_addr, .. } if contract_addr == "pair" => {
+            SystemResult::Ok(ContractResult::Ok(to_json_binary(&info).unwrap()))
+        },
+        _ => SystemResult::Err(SystemError::UnsupportedRequest { kind: "wasm".to_string() }),
+    });
+}
+
+fn pair_info(lp_token_address: &str, lp_token_supply: u128) -> PairInfoResponse {
+    PairInfoResponse {
+        token1_reserve: Uint128::new(3000),
+        token1_denom: cw20("reward"),
+        token2_reserve: Uint128::new(5000),
+        token2_denom: Denom::Native("ujuno".to_string()),
+        lp_token_supply: Uint128::new(lp_token_supply),
+        lp_token_address: lp_token_address.to_string(),
+    }
+}
+
+fn stake(deps: &mut Deps, now: u64, address: &str, amount: u128) {
+    execute(deps.as_mut(), env_at(now), mock_info("lptoken", &[]), ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: address.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_json_binary(&ReceiveMsg::Stake { lock_days: None }).unwrap(),
+    })).unwrap();
+}
+
+fn set_price(deps: &mut Deps, pool_id: u64, price: Decimal) {
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::SetPrice {
+        pool_id,
+        token: cw20("reward"),
+        price: Some(price),
+    }).unwrap();
+}
+
+fn tvl(deps: &Deps) -> Vec<TvlResponse> {
+    from_json(query(deps.as_ref(), env_at(1000), QueryMsg::Tvl {}).unwrap()).unwrap()
+}
+
+#[test]
+fn pool_pair_matches_the_lp_token() {
+    let mut deps = setup(100, Some("pair"));
+    mock_pair(&mut deps, pair_info("lptoken", 200));
+    let cfg = CONFIG.load(&deps.storage).unwrap();
+    let pool = POOLS.load(&deps.storage, 0).unwrap();
+    assert_eq!(pool_pair(deps.as_ref(), &cfg, &pool).unwrap(), Some(pair_info("lptoken", 200)));
+
+    // a pair for another LP token says nothing about this pool
+    mock_pair(&mut deps, pair_info("otherlp", 200));
+    assert_eq!(pool_pair(deps.as_ref(), &cfg, &pool).unwrap(), None);
+
+    // without a pair address the pair is never queried
+    let deps = setup(100, None);
+    let cfg = CONFIG.load(&deps.storage).unwrap();
+    assert_eq!(pool_pair(deps.as_ref(), &cfg, &pool).unwrap(), None);
+}
+
+#[test]
+fn stake_price_from_reserves() {
+    let deps = setup(100, Some("pair"));
+    let reward = cw20("reward");
+    let key = denom_key(&reward);
+
+    // 2 * 3000 / 200
+    let pair = pair_info("lptoken", 200);
+    assert_eq!(stake_price(deps.as_ref(), 0, Some(&pair), &key, &reward).unwrap(), Some(Decimal::from_ratio(30u128, 1u128)));
+    let juno = Denom::Native("ujuno".to_string());
+    assert_eq!(
+        stake_price(deps.as_ref(), 0, Some(&pair), &denom_key(&juno), &juno).unwrap(),
+        Some(Decimal::from_ratio(50u128, 1u128))
+    );
+
+    // an empty pair has no price
+    let empty = pair_info("lptoken", 0);
+    assert_eq!(stake_price(deps.as_ref(), 0, Some(&empty), &key, &reward).unwrap(), None);
+}
+
+#[test]
+fn stake_price_falls_back_to_set_price() {
+    let mut deps = setup(100, Some("pair"));
+    let reward = cw20("reward");
+    let key = denom_key(&reward);
+    assert_eq!(stake_price(deps.as_ref(), 0, None, &key, &reward).unwrap(), None);
+
+    set_price(&mut deps, 0, Decimal::percent(250));
+    assert_eq!(stake_price(deps.as_ref(), 0, None, &key, &reward).unwrap(), Some(Decimal::percent(250)));
+
+    // a pair without the reward token doesn't override the set price
+    let mut pair = pair_info("lptoken", 200);
+    pair.token1_denom = Denom::Native("uatom".to_string());
+    assert_eq!(stake_price(deps.as_ref(), 0, Some(&pair), &key, &reward).unwrap(), Some(Decimal::percent(250)));
+}
+
+#[test]
+fn tvl_values_stake_with_the_pair() {
+    let mut deps = setup(100, Some("pair"));
+    mock_pair(&mut deps, pair_info("lptoken", 200));
+    stake(&mut deps, 1000, "alice", 47);
+
+    assert_eq!(tvl(&deps), vec![TvlResponse {
+        pool_id: 0,
+        stake_token: cw20("lptoken"),
+        staked: Uint128::new(47),
+        values: vec![RewardAmount { token: cw20("reward"), amount: Uint128::new(1410) }],
+    }]);
+
+    // unbonding stake is still locked in the contract
+    execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::CreateUnstake {
+        pool_id: 0,
+        unstake_amount: Uint128::new(7),
+    }).unwrap();
+    assert_eq!(tvl(&deps)[0].values[0].amount, Uint128::new(1410));
+}
+
+#[test]
+fn tvl_without_pair_price() {
+    let mut deps = setup(100, Some("pair"));
+    stake(&mut deps, 1000, "alice", 47);
+
+    // empty pair, no set price: the pool has no value
+    mock_pair(&mut deps, pair_info("lptoken", 0));
+    assert_eq!(tvl(&deps)[0].values, vec![]);
+
+    // the pair trades another LP token, the set price is used
+    mock_pair(&mut deps, pair_info("otherlp", 200));
+    assert_eq!(tvl(&deps)[0].values, vec![]);
+    set_price(&mut deps, 0, Decimal::percent(150));
+    assert_eq!(tvl(&deps)[0].values, vec![RewardAmount { token: cw20("reward"), amount: Uint128::new(70) }]);
+}
+
+fn fund(deps: &mut Deps, now: u64, amount: u128, duration: u64) {
+    execute(deps.as_mut(), env_at(now), mock_info("reward", &[]), ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "owner".to_string(),
+        amount: Uint128::new(amount),
+        msg: to_json_binary(&ReceiveMsg::InitialFund { duration }).unwrap(),
+    })).unwrap();
+}
+
+/// Reward paid out by ClaimReward
+fn claim(deps: &mut Deps, now: u64, address: &str, pool_id: u64) -> u128 {
+    let res = execute(deps.as_mut(), env_at(now), mock_info(address, &[]), ExecuteMsg::ClaimReward { pool_id }).unwrap();
+    res.attributes.iter()
+        .filter(|a| a.key == "reward_amount")
+        .map(|a| a.value.parse::<u128>().unwrap())
+        .sum()
+}
+
+fn assert_near(actual: u128, expected: u128) {
+    assert!(actual <= expected && expected - actual <= 2, "{} is not {}", actual, expected);
+}
+
+#[test]
+fn staggered_claims_add_up_to_emission() {
+    let mut deps = setup(0, None);
+    // 10 reward per second from 1000 to 2000
+    fund(&mut deps, 1000, 10000, 1000);
+
+    stake(&mut deps, 1000, "alice", 100);
+    stake(&mut deps, 1200, "bob", 300);
+    execute(deps.as_mut(), env_at(1500), mock_info("alice", &[]), ExecuteMsg::CreateUnstake {
+        pool_id: 0,
+        unstake_amount: Uint128::new(50),
+    }).unwrap();
+    let bob = claim(&mut deps, 1600, "bob", 0);
+    stake(&mut deps, 1700, "carol", 50);
+
+    // alice 2000 + 100/400 * 3000 + 50/350 * 2000 + 50/400 * 3000
+    let alice = claim(&mut deps, 2500, "alice", 0);
+    assert_near(alice, 3410);
+    // bob 300/400 * 3000 + 300/350 * 2000 + 300/400 * 3000
+    let bob = bob + claim(&mut deps, 2500, "bob", 0);
+    assert_near(bob, 6214);
+    // carol 50/400 * 3000
+    let carol = claim(&mut deps, 2500, "carol", 0);
+    assert_near(carol, 375);
+    assert_near(alice + bob + carol, 10000);
+}
+
+#[test]
+fn set_pool_reweights_emission() {
+    let mut deps = setup(0, None);
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::AddPool {
+        stake_token: Denom::Native("ulp2".to_string()),
+        alloc_point: 300,
+    }).unwrap();
+    fund(&mut deps, 1000, 10000, 1000);
+    stake(&mut deps, 1000, "alice", 100);
+    execute(deps.as_mut(), env_at(1000), mock_info("carol", &coins(100, "ulp2")), ExecuteMsg::Stake {
+        lock_days: None,
+    }).unwrap();
+
+    // 100 : 300 for the first half, 100 : 100 for the second
+    execute(deps.as_mut(), env_at(1500), mock_info("owner", &[]), ExecuteMsg::SetPool {
+        pool_id: 1,
+        alloc_point: 100,
+    }).unwrap();
+
+    let alice = claim(&mut deps, 2000, "alice", 0);
+    let carol = claim(&mut deps, 2000, "carol", 1);
+    assert_near(alice, 1250 + 2500);
+    assert_near(carol, 3750 + 2500);
+    assert_near(alice + carol, 10000);
+}
+
+#[test]
+fn withdraw_reward_leaves_owed_and_remaining_reward() {
+    let mut deps = setup(0, None);
+    fund(&mut deps, 1000, 10000, 1000);
+
+    // nobody staked for the first 500 seconds, that emission is surplus
+    stake(&mut deps, 1500, "alice", 100);
+
+    // at 1800 alice is owed 3000 and 2000 is still to be emitted
+    let res = execute(deps.as_mut(), env_at(1800), mock_info("owner", &[]), ExecuteMsg::WithdrawReward {}).unwrap();
+    let token = REWARD_TOKENS.load(&deps.storage, denom_key(&cw20("reward"))).unwrap();
+    assert_eq!(token.reward_owed, Uint128::new(3000));
+    assert_eq!(token.reward_amount, Uint128::new(5000));
+    assert_eq!(res.attributes.iter().find(|a| a.key == "reward_amount").unwrap().value, "5000");
+
+    // nothing left to withdraw until alice claims what was earned
+    let res = execute(deps.as_mut(), env_at(1900), mock_info("owner", &[]), ExecuteMsg::WithdrawReward {}).unwrap();
+    assert!(res.messages.is_empty());
+    assert_near(claim(&mut deps, 2000, "alice", 0), 5000);
+
+    let token = REWARD_TOKENS.load(&deps.storage, denom_key(&cw20("reward"))).unwrap();
+    assert!(token.reward_owed.is_zero());
+    assert!(token.reward_amount.u128() <= 2);
+}
+
+/// Pool 1 staking the native "ulp" next to pool 0
+fn add_native_pool(deps: &mut Deps) {
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::AddPool {
+        stake_token: Denom::Native("ulp".to_string()),
+        alloc_point: 100,
+    }).unwrap();
+}
+
+fn stake_native(deps: &mut Deps, now: u64, address: &str, amount: u128) {
+    execute(deps.as_mut(), env_at(now), mock_info(address, &coins(amount, "ulp")), ExecuteMsg::Stake {
+        lock_days: None,
+    }).unwrap();
+}
+
+#[test]
+fn remove_staker_returns_principal() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    stake_native(&mut deps, 1000, "alice", 500);
+    execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::CreateUnstake {
+        pool_id: 1,
+        unstake_amount: Uint128::new(200),
+    }).unwrap();
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, coins(500, "ulp"));
+
+    // staked and unbonding principal is not stray
+    let recover = ExecuteMsg::RecoverTokens {
+        token: Denom::Native("ulp".to_string()),
+        amount: Uint128::new(500),
+        recipient: "owner".to_string(),
+    };
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover.clone()).unwrap_err();
+    assert_eq!(err, ContractError::UntrackedBalanceExceeded {});
+
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::RemoveStaker {
+        pool_id: 1,
+        address: Addr::unchecked("alice"),
+    }).unwrap();
+    assert_eq!(res.messages[0].msg, BankMsg::Send { to_address: "alice".to_string(), amount: coins(500, "ulp") }.into());
+    let pool = POOLS.load(&deps.storage, 1).unwrap();
+    assert!(pool.total_active_stake.is_zero() && pool.total_unbonding.is_zero());
+
+    // once paid out nothing is left to recover
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![]);
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover).unwrap_err();
+    assert_eq!(err, ContractError::UntrackedBalanceExceeded {});
+}
+
+fn stake_locked(deps: &mut Deps, now: u64, address: &str, amount: u128, lock_days: u64) {
+    execute(deps.as_mut(), env_at(now), mock_info("lptoken", &[]), ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: address.to_string(),
+        amount: Uint128::new(amount),
+        msg: to_json_binary(&ReceiveMsg::Stake { lock_days: Some(lock_days) }).unwrap(),
+    })).unwrap();
+}
+
+#[test]
+fn lock_boost_ends_at_lock_end() {
+    let mut deps = setup(0, None);
+    // 1 reward per second for 300 days
+    fund(&mut deps, 1000, 300 * 86400, 300 * 86400);
+    stake_locked(&mut deps, 1000, "alice", 100, 30);
+    stake(&mut deps, 1000, "bob", 100);
+
+    // 125 : 100 for the 30 days of the lock, 100 : 100 after it, nobody touched the pool in between
+    let end = 1000 + 300 * 86400;
+    let pending: PendingRewardResponse = from_json(query(deps.as_ref(), env_at(end), QueryMsg::PendingReward {
+        pool_id: 0,
+        address: "alice".to_string(),
+        at_time: None,
+    }).unwrap()).unwrap();
+    assert_near(pending.rewards[0].amount.u128(), 1440000 + 11664000);
+    assert_near(claim(&mut deps, end, "bob", 0), 1152000 + 11664000);
+    assert_eq!(POOLS.load(&deps.storage, 0).unwrap().total_weight, Uint128::new(200));
+    assert_near(claim(&mut deps, end, "alice", 0), 1440000 + 11664000);
+}
+
+#[test]
+fn unlocked_tier_must_be_1x() {
+    let mut deps = setup(0, None);
+    let update = |multiplier| ExecuteMsg::UpdateLockTiers {
+        lock_tiers: vec![LockTier { days: 0, multiplier }, LockTier { days: 30, multiplier: 125 }],
+        eta: 1000,
+    };
+    // unlocked stake weighs its amount, a boosted 0-day tier would be taken back on the next stake
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), update(150)).unwrap_err();
+    assert_eq!(err, ContractError::InvalidInput {});
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), update(100)).unwrap();
+}
+
+#[test]
+fn stake_for_joins_the_running_lock() {
+    let mut deps = setup(0, None);
+    stake_locked(&mut deps, 1000, "alice", 100, 30);
+
+    // bob adds to the lock at its 1.25x without pushing lock_end out
+    execute(deps.as_mut(), env_at(1000 + 10 * 86400), mock_info("lptoken", &[]), ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "bob".to_string(),
+        amount: Uint128::new(100),
+        msg: to_json_binary(&ReceiveMsg::StakeFor { recipient: "alice".to_string() }).unwrap(),
+    })).unwrap();
+    let alice = STAKERS.load(&deps.storage, (0, Addr::unchecked("alice"))).unwrap();
+    assert_eq!((alice.amount, alice.weight, alice.lock_days, alice.lock_end), (Uint128::new(200), Uint128::new(250), 30, 1000 + 30 * 86400));
+    assert_eq!(POOLS.load(&deps.storage, 0).unwrap().total_weight, Uint128::new(250));
+
+    // and the whole boost ends with it
+    stake(&mut deps, 1000 + 30 * 86400, "carol", 100);
+    let alice = STAKERS.load(&deps.storage, (0, Addr::unchecked("alice"))).unwrap();
+    assert_eq!((alice.weight, alice.lock_days), (Uint128::new(200), 0));
+    assert_eq!(POOLS.load(&deps.storage, 0).unwrap().total_weight, Uint128::new(300));
+}
+
+#[test]
+fn failing_hook_never_holds_up_a_withdrawal() {
+    let mut deps = setup(0, None);
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::AddHook { addr: "hook".to_string() }).unwrap();
+    stake(&mut deps, 1000, "alice", 100);
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::CreateUnstake {
+        pool_id: 0,
+        unstake_amount: Uint128::new(40),
+    }).unwrap();
+    // a stake change is reverted by its hook
+    let hook = StakeChangedHookMsg { pool_id: 0, address: "alice".to_string(), old: Uint128::new(100), new: Uint128::new(60), unbonding: Uint128::new(40) };
+    assert_eq!(res.messages, vec![SubMsg::new(hook.into_cosmos_msg("hook").unwrap())]);
+
+    // only unbonding moves on fetch, a failing hook is caught in reply
+    let id = res.attributes.iter().find(|a| a.key == "unstake_id").unwrap().value.parse().unwrap();
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::FetchUnstake { pool_id: 0, id }).unwrap();
+    let hook = StakeChangedHookMsg { pool_id: 0, address: "alice".to_string(), old: Uint128::new(60), new: Uint128::new(60), unbonding: Uint128::zero() };
+    assert_eq!(res.messages[1].msg, hook.into_cosmos_msg("hook").unwrap());
+    assert_eq!(res.messages[1].reply_on, ReplyOn::Error);
+}
+
+#[test]
+fn add_stakers_weighs_by_lock_tier() {
+    let mut deps = setup(0, None);
+    let staker = |address: &str, lock_days, lock_end| StakerInfo {
+        address: Addr::unchecked(address),
+        amount: Uint128::new(100),
+        weight: Uint128::new(1000),
+        lock_days,
+        lock_end,
+        rewards: vec![],
+        last_time: 1000,
+    };
+    let add = |stakers| ExecuteMsg::AddStakers { pool_id: 0, stakers };
+
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), add(vec![staker("alice", 7, 2000)])).unwrap_err();
+    assert_eq!(err, ContractError::LockTierNotFound {});
+    // a lock can't end later than its tier allows
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), add(vec![staker("alice", 30, 1000 + 31 * 86400)])).unwrap_err();
+    assert_eq!(err, ContractError::InvalidInput {});
+
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), add(vec![staker("alice", 30, 2000), staker("bob", 90, 500)])).unwrap();
+    let alice = STAKERS.load(&deps.storage, (0, Addr::unchecked("alice"))).unwrap();
+    assert_eq!((alice.weight, alice.lock_days), (Uint128::new(125), 30));
+    // bob's lock has passed already
+    let bob = STAKERS.load(&deps.storage, (0, Addr::unchecked("bob"))).unwrap();
+    assert_eq!((bob.weight, bob.lock_days), (Uint128::new(100), 0));
+    assert_eq!(POOLS.load(&deps.storage, 0).unwrap().total_weight, Uint128::new(225));
+}
+
+#[test]
+fn cancel_unstake_in_parts() {
+    let mut deps = setup(0, None);
+    stake(&mut deps, 1000, "alice", 100);
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::CreateUnstake {
+        pool_id: 0,
+        unstake_amount: Uint128::new(60),
+    }).unwrap();
+    let id: u64 = res.attributes.iter().find(|a| a.key == "unstake_id").unwrap().value.parse().unwrap();
+    let cancel = |amount: Option<u128>| ExecuteMsg::CancelUnstake { pool_id: 0, id, amount: amount.map(Uint128::new) };
+
+    execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), cancel(Some(20))).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), cancel(Some(50))).unwrap_err();
+    assert_eq!(err, ContractError::NotEnoughStake {});
+    let alice = STAKERS.load(&deps.storage, (0, Addr::unchecked("alice"))).unwrap();
+    assert_eq!((alice.amount, alice.weight), (Uint128::new(60), Uint128::new(60)));
+    let pool = POOLS.load(&deps.storage, 0).unwrap();
+    assert_eq!((pool.total_active_stake, pool.total_unbonding, pool.total_weight), (Uint128::new(60), Uint128::new(40), Uint128::new(60)));
+
+    // the rest can't join the position once it is locked
+    stake_locked(&mut deps, 1000, "alice", 10, 30);
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), cancel(None)).unwrap_err();
+    assert_eq!(err, ContractError::StillInLock {});
+    execute(deps.as_mut(), env_at(1000 + 30 * 86400), mock_info("alice", &[]), cancel(None)).unwrap();
+    let pool = POOLS.load(&deps.storage, 0).unwrap();
+    assert_eq!((pool.total_active_stake, pool.total_unbonding, pool.total_weight), (Uint128::new(110), Uint128::zero(), Uint128::new(110)));
+}
+
+#[test]
+fn remove_all_stakers_pages_by_limit() {
+    let mut deps = setup(0, None);
+    for address in ["alice", "bob", "carol"] {
+        stake(&mut deps, 1000, address, 100);
+    }
+    let remove = |start_after: Option<&str>| ExecuteMsg::RemoveAllStakers {
+        pool_id: 0,
+        start_after: start_after.map(|a| a.to_string()),
+        limit: Some(2),
+    };
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), remove(None)).unwrap();
+    assert!(!STAKERS.has(&deps.storage, (0, Addr::unchecked("bob"))));
+    assert!(STAKERS.has(&deps.storage, (0, Addr::unchecked("carol"))));
+
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), remove(Some("bob"))).unwrap();
+    assert!(POOLS.load(&deps.storage, 0).unwrap().total_active_stake.is_zero());
+}
+
+#[test]
+fn frequent_claims_keep_precision_on_18_decimal_stake() {
+    let mut deps = setup(0, None);
+    // 1000 reward per second against two million 18 decimal stake tokens
+    fund(&mut deps, 1000, 1_000_000_000, 1_000_000);
+    stake(&mut deps, 1000, "alice", 1_000_000_000_000_000_000_000_000);
+    stake(&mut deps, 1000, "bob", 1_000_000_000_000_000_000_000_000);
+
+    let alice: u128 = (1..=100).map(|i| claim(&mut deps, 1000 + i * 10, "alice", 0)).sum();
+    assert_near(alice, 500000);
+    assert_near(claim(&mut deps, 2000, "bob", 0), 500000);
+}
+
+#[test]
+fn migrate_refuses_the_old_layout() {
+    let mut deps = setup(0, None);
+    migrate(deps.as_mut(), env_at(1000), MigrateMsg {}).unwrap();
+
+    cw2::set_contract_version(&mut deps.storage, "marbleincentive", "0.0.1").unwrap();
+    let err = migrate(deps.as_mut(), env_at(1000), MigrateMsg {}).unwrap_err();
+    assert_eq!(err, ContractError::CannotMigrateVersion { previous_version: "0.0.1".to_string() });
+}
+
+fn roles(deps: &Deps, address: &str) -> Vec<Role> {
+    let res: RolesResponse = from_json(query(deps.as_ref(), env_at(1000), QueryMsg::Roles { address: address.to_string() }).unwrap()).unwrap();
+    res.roles
+}
+
+#[test]
+fn roles_query_reports_what_an_address_can_do() {
+    let mut deps = setup(0, None);
+    let all = vec![Role::Admin, Role::Operator, Role::Funder, Role::Migrator];
+    assert_eq!(roles(&deps, "owner"), all);
+    assert!(roles(&deps, "alice").is_empty());
+
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::GrantRole {
+        address: "alice".to_string(),
+        role: Role::Funder,
+    }).unwrap();
+    assert_eq!(roles(&deps, "alice"), vec![Role::Funder]);
+
+    // granted roles are frozen along with the owner
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::RenounceOwnership {}).unwrap();
+    assert!(roles(&deps, "owner").is_empty());
+    assert!(roles(&deps, "alice").is_empty());
+}
+
+/// Queues `msg` with the owner and applies it right away, setup has no timelock
+fn queue_and_execute(deps: &mut Deps, now: u64, msg: ExecuteMsg) {
+    let id = queue(deps, now, msg).unwrap();
+    execute(deps.as_mut(), env_at(now), mock_info("owner", &[]), ExecuteMsg::ExecuteConstantsChange { id }).unwrap();
+}
+
+fn set_unbonding(deps: &mut Deps, now: u64, lock_days: u64, percent: u64, decay: bool, destination: PenaltyDestination) {
+    queue_and_execute(deps, now, ExecuteMsg::UpdateConstants {
+        reward_interval: 100,
+        delta_time: 0,
+        lock_days,
+        early_unstake_penalty: EarlyUnstakePenalty { percent, decay, destination },
+        min_delay: 0,
+        eta: now,
+    });
+}
+
+/// Unstake id returned by CreateUnstake
+fn unstake(deps: &mut Deps, now: u64, address: &str, pool_id: u64, amount: u128) -> u64 {
+    let res = execute(deps.as_mut(), env_at(now), mock_info(address, &[]), ExecuteMsg::CreateUnstake {
+        pool_id,
+        unstake_amount: Uint128::new(amount),
+    }).unwrap();
+    res.attributes.iter().find(|a| a.key == "unstake_id").unwrap().value.parse().unwrap()
+}
+
+fn send(to: &str, amount: u128) -> SubMsg {
+    SubMsg::new(BankMsg::Send { to_address: to.to_string(), amount: coins(amount, "ulp") })
+}
+
+#[test]
+fn fetch_early_penalty_decays_over_the_entry_period() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    let treasury = || PenaltyDestination::Treasury(Addr::unchecked("treasury"));
+    set_unbonding(&mut deps, 1000, 10, 20, true, treasury());
+    stake_native(&mut deps, 1000, "alice", 1000);
+    let id = unstake(&mut deps, 1000, "alice", 1, 500);
+
+    // a longer unbonding period set later doesn't stretch the decay of the entry
+    set_unbonding(&mut deps, 1000, 20, 20, true, treasury());
+    let res = execute(deps.as_mut(), env_at(1000 + 5 * 86400), mock_info("alice", &[]), ExecuteMsg::FetchEarly { pool_id: 1, id }).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 450), send("treasury", 50)]);
+    assert!(POOLS.load(&deps.storage, 1).unwrap().total_unbonding.is_zero());
+
+    // a matured entry pays no penalty
+    let id = unstake(&mut deps, 1000 + 5 * 86400, "alice", 1, 100);
+    let res = execute(deps.as_mut(), env_at(1000 + 25 * 86400), mock_info("alice", &[]), ExecuteMsg::FetchEarly { pool_id: 1, id }).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 100)]);
+
+    // 100 percent keeps early exit closed
+    set_unbonding(&mut deps, 1000, 20, 100, false, treasury());
+    let id = unstake(&mut deps, 1000 + 25 * 86400, "alice", 1, 100);
+    let err = execute(deps.as_mut(), env_at(1000 + 25 * 86400), mock_info("alice", &[]), ExecuteMsg::FetchEarly { pool_id: 1, id }).unwrap_err();
+    assert_eq!(err, ContractError::EarlyExitClosed {});
+}
+
+#[test]
+fn fetch_early_penalty_burns_or_redistributes() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    set_unbonding(&mut deps, 1000, 10, 20, false, PenaltyDestination::Burn);
+    stake_native(&mut deps, 1000, "alice", 1000);
+    stake_native(&mut deps, 1000, "bob", 1000);
+    let id = unstake(&mut deps, 1000, "alice", 1, 500);
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::FetchEarly { pool_id: 1, id }).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 400), SubMsg::new(BankMsg::Burn { amount: coins(100, "ulp") })]);
+
+    // the remaining 500 : 1000 weight share the penalty
+    set_unbonding(&mut deps, 1000, 10, 30, false, PenaltyDestination::Redistribute);
+    let id = unstake(&mut deps, 1000, "alice", 1, 250);
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::FetchEarly { pool_id: 1, id }).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 175)]);
+    assert_eq!(POOLS.load(&deps.storage, 1).unwrap().penalty_amount, Uint128::new(75));
+
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("bob", &[]), ExecuteMsg::ClaimReward { pool_id: 1 }).unwrap();
+    assert_eq!(res.messages, vec![send("bob", 60)]);
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), ExecuteMsg::ClaimReward { pool_id: 1 }).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 15)]);
+    assert!(POOLS.load(&deps.storage, 1).unwrap().penalty_amount.is_zero());
+}
+
+fn unstaking(deps: &Deps, address: &str, pool_id: u64) -> Vec<(u64, Uint128, u64)> {
+    from_json(query(deps.as_ref(), env_at(1000), QueryMsg::Unstaking { pool_id, address: Addr::unchecked(address) }).unwrap()).unwrap()
+}
+
+#[test]
+fn unstake_ids_stay_put_when_another_entry_goes() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    set_unbonding(&mut deps, 1000, 1, 100, false, PenaltyDestination::Burn);
+    stake_native(&mut deps, 1000, "alice", 600);
+    let first = unstake(&mut deps, 1000, "alice", 1, 100);
+    let second = unstake(&mut deps, 2000, "alice", 1, 200);
+    let third = unstake(&mut deps, 3000, "alice", 1, 300);
+
+    let fetch = |id| ExecuteMsg::FetchUnstake { pool_id: 1, id };
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), fetch(first)).unwrap_err();
+    assert_eq!(err, ContractError::StillLocked {});
+    let res = execute(deps.as_mut(), env_at(2000 + 86400), mock_info("alice", &[]), fetch(second)).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 200)]);
+    let err = execute(deps.as_mut(), env_at(2000 + 86400), mock_info("alice", &[]), fetch(second)).unwrap_err();
+    assert_eq!(err, ContractError::NotCreatedUnstaking {});
+
+    // the other entries keep their id and amount
+    assert_eq!(unstaking(&deps, "alice", 1), vec![
+        (first, Uint128::new(100), 1000 + 86400),
+        (third, Uint128::new(300), 3000 + 86400),
+    ]);
+    let res = execute(deps.as_mut(), env_at(3000 + 86400), mock_info("alice", &[]), fetch(third)).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 300)]);
+    let pool = POOLS.load(&deps.storage, 1).unwrap();
+    assert_eq!(pool.total_unbonding, Uint128::new(100));
+}
+
+#[test]
+fn fetch_all_matured_pays_only_matured_entries() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    set_unbonding(&mut deps, 1000, 1, 100, false, PenaltyDestination::Burn);
+    stake_native(&mut deps, 1000, "alice", 600);
+    unstake(&mut deps, 1000, "alice", 1, 100);
+    unstake(&mut deps, 2000, "alice", 1, 200);
+    let late = unstake(&mut deps, 1000 + 86400, "alice", 1, 300);
+
+    let fetch_all = ExecuteMsg::FetchAllMatured { pool_id: 1 };
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), fetch_all.clone()).unwrap_err();
+    assert_eq!(err, ContractError::StillLocked {});
+
+    // both matured entries go out in a single transfer
+    let res = execute(deps.as_mut(), env_at(2000 + 86400), mock_info("alice", &[]), fetch_all.clone()).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 300)]);
+    assert_eq!(unstaking(&deps, "alice", 1), vec![(late, Uint128::new(300), 1000 + 2 * 86400)]);
+    assert_eq!(POOLS.load(&deps.storage, 1).unwrap().total_unbonding, Uint128::new(300));
+
+    let res = execute(deps.as_mut(), env_at(1000 + 2 * 86400), mock_info("alice", &[]), fetch_all.clone()).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 300)]);
+    let err = execute(deps.as_mut(), env_at(1000 + 2 * 86400), mock_info("alice", &[]), fetch_all).unwrap_err();
+    assert_eq!(err, ContractError::NotCreatedUnstaking {});
+}
+
+#[test]
+fn emergency_withdraw_returns_locked_stake_and_unbondings() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    fund(&mut deps, 1000, 10000, 1000);
+    stake_native(&mut deps, 1000, "alice", 400);
+    unstake(&mut deps, 1000, "alice", 1, 100);
+    execute(deps.as_mut(), env_at(1000), mock_info("alice", &coins(600, "ulp")), ExecuteMsg::Stake { lock_days: Some(30) }).unwrap();
+
+    let emergency = ExecuteMsg::EmergencyWithdraw { pool_id: 1 };
+    let err = execute(deps.as_mut(), env_at(1500), mock_info("alice", &[]), emergency.clone()).unwrap_err();
+    assert_eq!(err, ContractError::NotDisabled {});
+
+    execute(deps.as_mut(), env_at(1500), mock_info("owner", &[]), ExecuteMsg::SetEnabled { enabled: false }).unwrap();
+    let res = execute(deps.as_mut(), env_at(1500), mock_info("alice", &[]), emergency.clone()).unwrap();
+    assert_eq!(res.messages, vec![send("alice", 1000)]);
+    let pool = POOLS.load(&deps.storage, 1).unwrap();
+    assert!(pool.total_active_stake.is_zero() && pool.total_unbonding.is_zero() && pool.total_weight.is_zero());
+    // the forfeited reward is no longer owed, up to rounding
+    assert!(REWARD_TOKENS.load(&deps.storage, denom_key(&cw20("reward"))).unwrap().reward_owed.u128() <= 2);
+
+    let err = execute(deps.as_mut(), env_at(1500), mock_info("alice", &[]), emergency).unwrap_err();
+    assert_eq!(err, ContractError::NoStaked {});
+}
+
+#[test]
+fn recover_tokens_takes_only_the_untracked_balance() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    stake_native(&mut deps, 1000, "alice", 500);
+    deps.querier.update_balance(MOCK_CONTRACT_ADDR, vec![Coin::new(700, "ulp"), Coin::new(50, "uatom")]);
+    let recover = |denom: &str, amount| ExecuteMsg::RecoverTokens {
+        token: Denom::Native(denom.to_string()),
+        amount: Uint128::new(amount),
+        recipient: "treasury".to_string(),
+    };
+
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), recover("uatom", 50)).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover("uatom", 51)).unwrap_err();
+    assert_eq!(err, ContractError::UntrackedBalanceExceeded {});
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover("uatom", 50)).unwrap();
+    assert_eq!(res.messages, vec![SubMsg::new(BankMsg::Send { to_address: "treasury".to_string(), amount: coins(50, "uatom") })]);
+
+    // of a pool token only what is above the stake
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover("ulp", 201)).unwrap_err();
+    assert_eq!(err, ContractError::UntrackedBalanceExceeded {});
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), recover("ulp", 200)).unwrap();
+    assert_eq!(res.messages, vec![send("treasury", 200)]);
+}
+
+#[test]
+fn ownership_moves_only_when_accepted_in_time() {
+    let mut deps = setup(0, None);
+    let pause = ExecuteMsg::SetEnabled { enabled: false };
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::ProposeOwner {
+        new_owner: "alice".to_string(),
+        expiry: Some(Expiration::AtTime(Timestamp::from_seconds(2000))),
+    }).unwrap();
+
+    let err = execute(deps.as_mut(), env_at(1500), mock_info("bob", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+    assert_eq!(err, ContractError::OwnershipProposalExpired {});
+    // the proposal alone hands over nothing
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), pause.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    execute(deps.as_mut(), env_at(2000), mock_info("owner", &[]), ExecuteMsg::ProposeOwner {
+        new_owner: "alice".to_string(),
+        expiry: None,
+    }).unwrap();
+    execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), ExecuteMsg::AcceptOwnership {}).unwrap();
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("owner", &[]), pause.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), ExecuteMsg::AcceptOwnership {}).unwrap_err();
+    assert_eq!(err, ContractError::NoPendingOwner {});
+
+    // renouncing freezes every owner action for good
+    execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), ExecuteMsg::RenounceOwnership {}).unwrap();
+    assert_eq!(CONFIG.load(&deps.storage).unwrap().owner, None);
+    let err = execute(deps.as_mut(), env_at(2000), mock_info("alice", &[]), pause).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+fn set_role(deps: &mut Deps, sender: &str, address: &str, role: Role, grant: bool) -> Result<(), ContractError> {
+    let msg = if grant {
+        ExecuteMsg::GrantRole { address: address.to_string(), role }
+    } else {
+        ExecuteMsg::RevokeRole { address: address.to_string(), role }
+    };
+    execute(deps.as_mut(), env_at(1000), mock_info(sender, &[]), msg).map(|_| ())
+}
+
+#[test]
+fn roles_gate_what_they_cover() {
+    let mut deps = setup(0, None);
+    let pause = ExecuteMsg::SetEnabled { enabled: false };
+    let add_pool = ExecuteMsg::AddPool { stake_token: Denom::Native("ulp".to_string()), alloc_point: 100 };
+
+    assert_eq!(set_role(&mut deps, "alice", "alice", Role::Admin, true).unwrap_err(), ContractError::Unauthorized {});
+    set_role(&mut deps, "owner", "alice", Role::Operator, true).unwrap();
+    execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), pause.clone()).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), add_pool.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // a funder funds reward programs and nothing else
+    set_role(&mut deps, "owner", "bob", Role::Funder, true).unwrap();
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::SetEnabled { enabled: true }).unwrap();
+    execute(deps.as_mut(), env_at(1000), mock_info("reward", &[]), ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: "bob".to_string(),
+        amount: Uint128::new(1000),
+        msg: to_json_binary(&ReceiveMsg::InitialFund { duration: 1000 }).unwrap(),
+    })).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("bob", &[]), pause.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+
+    // an admin can do everything the other roles can, and hand them out
+    set_role(&mut deps, "owner", "carol", Role::Admin, true).unwrap();
+    execute(deps.as_mut(), env_at(1000), mock_info("carol", &[]), add_pool).unwrap();
+    set_role(&mut deps, "carol", "alice", Role::Operator, false).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), pause).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+}
+
+/// Change id queued by `msg`
+fn queue(deps: &mut Deps, now: u64, msg: ExecuteMsg) -> Result<u64, ContractError> {
+    let res = execute(deps.as_mut(), env_at(now), mock_info("owner", &[]), msg)?;
+    Ok(res.attributes.iter().find(|a| a.key == "change_id").unwrap().value.parse().unwrap())
+}
+
+fn constants(reward_interval: u64, min_delay: u64, eta: u64) -> ExecuteMsg {
+    ExecuteMsg::UpdateConstants {
+        reward_interval,
+        delta_time: 0,
+        lock_days: 0,
+        early_unstake_penalty: EarlyUnstakePenalty { percent: 100, decay: false, destination: PenaltyDestination::Burn },
+        min_delay,
+        eta,
+    }
+}
+
+#[test]
+fn constants_changes_wait_for_their_eta() {
+    let mut deps = setup(0, None);
+    let apply = |id| ExecuteMsg::ExecuteConstantsChange { id };
+    queue_and_execute(&mut deps, 1000, constants(100, 86400, 1000));
+
+    let err = queue(&mut deps, 1000, constants(100, 0, 1000 + 86399)).unwrap_err();
+    assert_eq!(err, ContractError::EtaTooSoon { min_delay: 86400 });
+    let id = queue(&mut deps, 1000, constants(100, 0, 1000 + 86400)).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000 + 86399), mock_info("owner", &[]), apply(id)).unwrap_err();
+    assert_eq!(err, ContractError::TimelockNotExpired {});
+
+    // a cancelled change is gone for good
+    let cancelled = queue(&mut deps, 1000, constants(200, 0, 1000 + 86400)).unwrap();
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), ExecuteMsg::CancelConstantsChange { id: cancelled }).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000 + 86400), mock_info("owner", &[]), apply(cancelled)).unwrap_err();
+    assert_eq!(err, ContractError::ConstantsChangeNotFound {});
+
+    execute(deps.as_mut(), env_at(1000 + 86400), mock_info("owner", &[]), apply(id)).unwrap();
+    assert_eq!(CONFIG.load(&deps.storage).unwrap().min_delay, 0);
+    let err = execute(deps.as_mut(), env_at(1000 + 86400), mock_info("owner", &[]), apply(id)).unwrap_err();
+    assert_eq!(err, ContractError::ConstantsChangeNotFound {});
+}
+
+#[test]
+fn constants_changes_never_emit_more_than_funded() {
+    let mut deps = setup(0, None);
+    // 1000 reward per 100 seconds
+    fund(&mut deps, 1000, 10000, 1000);
+    let update_token = |daily_reward_amount| ExecuteMsg::UpdateRewardToken {
+        token: cw20("reward"),
+        daily_reward_amount: Uint128::new(daily_reward_amount),
+        apy_prefix: Uint128::new(1),
+        eta: 1500,
+    };
+
+    let id = queue(&mut deps, 1500, update_token(1001)).unwrap();
+    let err = execute(deps.as_mut(), env_at(1500), mock_info("owner", &[]), ExecuteMsg::ExecuteConstantsChange { id }).unwrap_err();
+    assert_eq!(err, ContractError::NotEnoughReward {});
+    // a shorter reward_interval speeds every program up just the same
+    let id = queue(&mut deps, 1500, constants(99, 0, 1500)).unwrap();
+    let err = execute(deps.as_mut(), env_at(1500), mock_info("owner", &[]), ExecuteMsg::ExecuteConstantsChange { id }).unwrap_err();
+    assert_eq!(err, ContractError::NotEnoughReward {});
+
+    queue_and_execute(&mut deps, 1500, update_token(500));
+    let token = REWARD_TOKENS.load(&deps.storage, denom_key(&cw20("reward"))).unwrap();
+    assert_eq!(token.daily_reward_amount, Uint128::new(500));
+}
+
+fn voting_power(deps: &Deps, address: &str, height: Option<u64>) -> u128 {
+    let res: VotingPowerAtHeightResponse = from_json(query(deps.as_ref(), env_at(5000), QueryMsg::VotingPowerAtHeight {
+        address: address.to_string(),
+        height,
+        pool_id: Some(1),
+    }).unwrap()).unwrap();
+    res.power.u128()
+}
+
+fn total_power(deps: &Deps, height: Option<u64>) -> u128 {
+    let res: TotalPowerAtHeightResponse = from_json(query(deps.as_ref(), env_at(5000), QueryMsg::TotalPowerAtHeight {
+        height,
+        pool_id: Some(1),
+    }).unwrap()).unwrap();
+    res.power.u128()
+}
+
+#[test]
+fn voting_power_follows_active_stake_by_height() {
+    let mut deps = setup(0, None);
+    add_native_pool(&mut deps);
+    stake_native(&mut deps, 1000, "alice", 100);
+    stake_native(&mut deps, 1100, "bob", 50);
+    unstake(&mut deps, 1200, "alice", 1, 40);
+    execute(deps.as_mut(), env_at(1300), mock_info("owner", &[]), ExecuteMsg::RemoveStaker {
+        pool_id: 1,
+        address: Addr::unchecked("bob"),
+    }).unwrap();
+
+    // a change counts from the block after it, unbonding stake has no say
+    assert_eq!((voting_power(&deps, "alice", Some(1000)), total_power(&deps, Some(1000))), (0, 0));
+    assert_eq!((voting_power(&deps, "alice", Some(1001)), total_power(&deps, Some(1001))), (100, 100));
+    assert_eq!((voting_power(&deps, "bob", Some(1101)), total_power(&deps, Some(1101))), (50, 150));
+    assert_eq!((voting_power(&deps, "alice", Some(1201)), total_power(&deps, Some(1201))), (60, 110));
+    assert_eq!((voting_power(&deps, "bob", Some(1300)), voting_power(&deps, "bob", Some(1301))), (50, 0));
+    assert_eq!((voting_power(&deps, "alice", None), total_power(&deps, None)), (60, 60));
+}
+
+#[test]
+fn hooks_hear_every_stake_change_until_removed() {
+    let mut deps = setup(0, None);
+    let hook = |old: u128, new: u128, unbonding: u128| {
+        StakeChangedHookMsg {
+            pool_id: 0,
+            address: "alice".to_string(),
+            old: Uint128::new(old),
+            new: Uint128::new(new),
+            unbonding: Uint128::new(unbonding),
+        }
+        .into_cosmos_msg("hook")
+        .unwrap()
+    };
+    let stake_msg = |sender: &str| ExecuteMsg::Receive(Cw20ReceiveMsg {
+        sender: sender.to_string(),
+        amount: Uint128::new(100),
+        msg: to_json_binary(&ReceiveMsg::Stake { lock_days: None }).unwrap(),
+    });
+    let add_hook = ExecuteMsg::AddHook { addr: "hook".to_string() };
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), add_hook.clone()).unwrap_err();
+    assert_eq!(err, ContractError::Unauthorized {});
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), add_hook.clone()).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), add_hook).unwrap_err();
+    assert_eq!(err, ContractError::HookAlreadyRegistered {});
+
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("lptoken", &[]), stake_msg("alice")).unwrap();
+    assert_eq!(res.messages, vec![SubMsg::new(hook(0, 100, 0))]);
+    let id = unstake(&mut deps, 1000, "alice", 0, 30);
+    let cancel = ExecuteMsg::CancelUnstake { pool_id: 0, id, amount: Some(Uint128::new(10)) };
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("alice", &[]), cancel).unwrap();
+    assert_eq!(res.messages, vec![SubMsg::new(hook(70, 80, 20))]);
+
+    // a removed staker leaves even if the hook fails
+    let remove = ExecuteMsg::RemoveStaker { pool_id: 0, address: Addr::unchecked("alice") };
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), remove).unwrap();
+    assert_eq!(res.messages[1], SubMsg::reply_on_error(hook(80, 0, 0), 1));
+    let failed = Reply { id: 1, result: SubMsgResult::Err("hook failed".to_string()) };
+    assert_eq!(reply(deps.as_mut(), env_at(1000), failed).unwrap().attributes[0].value, "hook_failed");
+
+    let remove_hook = ExecuteMsg::RemoveHook { addr: "hook".to_string() };
+    execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), remove_hook.clone()).unwrap();
+    let err = execute(deps.as_mut(), env_at(1000), mock_info("owner", &[]), remove_hook).unwrap_err();
+    assert_eq!(err, ContractError::HookNotRegistered {});
+    let res = execute(deps.as_mut(), env_at(1000), mock_info("lptoken", &[]), stake_msg("bob")).unwrap();
+    assert!(res.messages.is_empty());
+}